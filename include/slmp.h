@@ -17,31 +17,55 @@ extern "C" {
 
 typedef void* Slmp;
 
+//返回值
+//0: 执行成功
+//正数: PLC返回的结束代码
+//负数: 其它错误
+#define SLMP_OK 0
+#define SLMP_ERR_NOT_CONNECTED -1        //句柄无效或未连接
+#define SLMP_ERR_IO -2                   //通信错误
+#define SLMP_ERR_TIMEOUT -3              //等待响应超时
+#define SLMP_ERR_MALFORMED_FRAME -4      //响应报文结构不正确
+#define SLMP_ERR_DESTINATION_MISMATCH -5 //响应的目标地址与请求不一致
+#define SLMP_ERR_INVALID_REQUEST -6      //请求参数不正确
+#define SLMP_ERR_DISCONNECTED -7         //连接已被对方关闭或中断
+#define SLMP_ERR_MODEL_MISMATCH -8       //PLC型号与期望的不一致
+
 //连接失败返回 null
 API_PREFIX Slmp slmp_connect(const char* ip,uint16_t port);
 
 //调用此函数断开连接后，还会释放内部资源。
-//所以重复调用此函数会使程序崩溃
+//slmp 为 NULL 或已经释放时不做任何操作,可以重复调用
 API_PREFIX void slmp_shutdown(Slmp slmp);
 
 //批量读取字软元件
-//dev  1:保持寄存器D, 2:文件寄存器R
+//dev  1:保持寄存器D, 2:文件寄存器R, 3:文件寄存器ZR, 4:链接寄存器W, 5:特殊寄存器SD, 6:链接特殊寄存器SW,
+//     7:定时器当前值TN, 8:计数器当前值CN, 9:累计定时器当前值STN, 10:变址寄存器Z
 //执行成功返回 0
 API_PREFIX int32_t slmp_read_words(Slmp slmp,uint32_t head_number,uint16_t dev,uint16_t number,uint16_t* data);
 
 //批量读取位软元件
-//dev  1: 内部继电器M, 2: 输入继电器X, 3: 输出继电器Y
+//dev  1: 内部继电器M, 2: 输入继电器X, 3: 输出继电器Y, 4: 链接继电器B, 5: 锁存继电器L, 6: 报警器F,
+//     7: 变址继电器V, 8: 特殊继电器SM, 9: 链接特殊继电器SB, 10: 直接访问输入DX, 11: 直接访问输出DY,
+//     12: 定时器触点TS, 13: 定时器线圈TC, 14: 计数器触点CS, 15: 计数器线圈CC,
+//     16: 累计定时器触点STS, 17: 累计定时器线圈STC
+//data 中每个元素为 0 或 1
 //执行成功返回 0
 API_PREFIX int32_t slmp_read_bits(Slmp slmp,uint32_t head_number,uint16_t dev,uint16_t number, uint8_t* data);
 
 //批量写入字软元件
-//dev  1:保持寄存器D, 2:文件寄存器R
+//dev  1:保持寄存器D, 2:文件寄存器R, 3:文件寄存器ZR, 4:链接寄存器W, 5:特殊寄存器SD, 6:链接特殊寄存器SW,
+//     7:定时器当前值TN, 8:计数器当前值CN, 9:累计定时器当前值STN, 10:变址寄存器Z
 //执行成功返回 0
 API_PREFIX int32_t slmp_write_words(Slmp slmp,uint32_t head_number,uint16_t dev,uint16_t number,const uint16_t* data);
 
 //批量写入位软元件
-//dev  1: 内部继电器M, 2: 输入继电器X, 3: 输出继电器Y
+//dev  1: 内部继电器M, 2: 输入继电器X, 3: 输出继电器Y, 4: 链接继电器B, 5: 锁存继电器L, 6: 报警器F,
+//     7: 变址继电器V, 8: 特殊继电器SM, 9: 链接特殊继电器SB, 10: 直接访问输入DX, 11: 直接访问输出DY,
+//     12: 定时器触点TS, 13: 定时器线圈TC, 14: 计数器触点CS, 15: 计数器线圈CC,
+//     16: 累计定时器触点STS, 17: 累计定时器线圈STC
 //执行成功返回 0
+//TS, CS, STS 为只读, 返回 SLMP_ERR_INVALID_REQUEST
 API_PREFIX int32_t slmp_write_bits(Slmp slmp,uint32_t head_number,uint16_t dev,uint16_t number,const uint8_t* data);
 
 #ifdef __cplusplus
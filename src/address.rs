@@ -0,0 +1,169 @@
+// 软元件地址
+// 与 GX Works 相同的写法, 如 "D100", "X1F", "W1A0", "ZR12000"
+// X, Y, B, W, SB, SW, DX, DY 的编号为16进制, 其它为10进制
+use crate::error::SlmpError;
+use crate::slmp_core::{Device, DeviceBit, DeviceWord, BIT_DEVICES, WORD_DEVICES};
+use std::fmt;
+use std::str::FromStr;
+
+//软元件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceType {
+    Word(DeviceWord), //字软元件
+    Bit(DeviceBit),   //位软元件
+}
+
+impl Device for DeviceType {
+    fn code(&self) -> u8 {
+        match self {
+            DeviceType::Word(d) => d.code(),
+            DeviceType::Bit(d) => d.code(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            DeviceType::Word(d) => d.name(),
+            DeviceType::Bit(d) => d.name(),
+        }
+    }
+
+    fn ascii(&self) -> &'static str {
+        match self {
+            DeviceType::Word(d) => d.ascii(),
+            DeviceType::Bit(d) => d.ascii(),
+        }
+    }
+
+    fn is_hex(&self) -> bool {
+        match self {
+            DeviceType::Word(d) => d.is_hex(),
+            DeviceType::Bit(d) => d.is_hex(),
+        }
+    }
+
+    fn iqr_only(&self) -> bool {
+        match self {
+            DeviceType::Word(d) => d.iqr_only(),
+            DeviceType::Bit(d) => d.iqr_only(),
+        }
+    }
+
+    fn is_read_only(&self) -> bool {
+        match self {
+            DeviceType::Word(d) => d.is_read_only(),
+            DeviceType::Bit(d) => d.is_read_only(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceAddress {
+    pub device: DeviceType, //软元件
+    pub number: u32,        //软元件编号
+}
+
+impl DeviceAddress {
+    pub fn word(device: DeviceWord, number: u32) -> DeviceAddress {
+        DeviceAddress {
+            device: DeviceType::Word(device),
+            number,
+        }
+    }
+
+    pub fn bit(device: DeviceBit, number: u32) -> DeviceAddress {
+        DeviceAddress {
+            device: DeviceType::Bit(device),
+            number,
+        }
+    }
+
+    pub fn is_bit(&self) -> bool {
+        matches!(self.device, DeviceType::Bit(_))
+    }
+
+    //编号是否为16进制
+    pub fn is_hex(&self) -> bool {
+        self.device.is_hex()
+    }
+
+    //字软元件, 位软元件时返回 Err(SlmpError::InvalidRequest)
+    pub(crate) fn as_word(&self) -> Result<(u32, DeviceWord), SlmpError> {
+        match self.device {
+            DeviceType::Word(d) => Ok((self.number, d)),
+            DeviceType::Bit(_) => Err(SlmpError::InvalidRequest(format!(
+                "{} is not a word device",
+                self
+            ))),
+        }
+    }
+
+    //位软元件, 字软元件时返回 Err(SlmpError::InvalidRequest)
+    pub(crate) fn as_bit(&self) -> Result<(u32, DeviceBit), SlmpError> {
+        match self.device {
+            DeviceType::Bit(d) => Ok((self.number, d)),
+            DeviceType::Word(_) => Err(SlmpError::InvalidRequest(format!(
+                "{} is not a bit device",
+                self
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for DeviceAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_hex() {
+            write!(f, "{}{:X}", self.device.name(), self.number)
+        } else {
+            write!(f, "{}{}", self.device.name(), self.number)
+        }
+    }
+}
+
+impl FromStr for DeviceAddress {
+    type Err = SlmpError;
+
+    //不区分大小写, 按最长的软元件名称匹配, 如 "DX10" 为 DX 而不是 D
+    fn from_str(s: &str) -> Result<DeviceAddress, SlmpError> {
+        let invalid = || SlmpError::InvalidRequest(format!("invalid device address {:?}", s));
+        let upper = s.trim().to_ascii_uppercase();
+        let device = WORD_DEVICES
+            .iter()
+            .map(|d| DeviceType::Word(*d))
+            .chain(BIT_DEVICES.iter().map(|d| DeviceType::Bit(*d)))
+            .filter(|d| upper.starts_with(d.name()))
+            .max_by_key(|d| d.name().len())
+            .ok_or_else(invalid)?;
+        let digits = &upper[device.name().len()..];
+        let radix = if device.is_hex() { 16 } else { 10 };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return Err(invalid());
+        }
+        let number = u32::from_str_radix(digits, radix).map_err(|_| invalid())?;
+        Ok(DeviceAddress { device, number })
+    }
+}
+
+#[test]
+fn test_device_address() {
+    let cases = [
+        ("D100", DeviceAddress::word(DeviceWord::D, 100)),
+        ("X1F", DeviceAddress::bit(DeviceBit::X, 0x1F)),
+        ("W1A0", DeviceAddress::word(DeviceWord::W, 0x1A0)),
+        ("ZR12000", DeviceAddress::word(DeviceWord::ZR, 12000)),
+        ("DX10", DeviceAddress::bit(DeviceBit::DX, 0x10)),
+        ("STS3", DeviceAddress::bit(DeviceBit::STS, 3)),
+        ("LZ1", DeviceAddress::word(DeviceWord::LZ, 1)),
+    ];
+    for (s, addr) in cases.iter() {
+        assert_eq!(s.parse::<DeviceAddress>().unwrap(), *addr);
+        assert_eq!(addr.to_string(), *s);
+    }
+    assert_eq!(
+        "sb0a".parse::<DeviceAddress>().unwrap(),
+        DeviceAddress::bit(DeviceBit::SB, 0x0A)
+    );
+    assert!("D1A".parse::<DeviceAddress>().is_err());
+    assert!("Q100".parse::<DeviceAddress>().is_err());
+    assert!("M".parse::<DeviceAddress>().is_err());
+}
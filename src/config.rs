@@ -0,0 +1,107 @@
+// 连接配置
+// 在 connect 之前设置, 对之后建立的连接有效
+use crate::error::{ErrorKind, SlmpError};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlmpConfig {
+    pub connect_timeout: Duration,          //建立 TCP 连接的超时
+    pub response_timeout: Duration,         //从发送请求到收到完整响应的总超时, 包括写入和 UDP 重发
+    pub write_timeout: Duration,            //写入请求报文的超时, 超时后连接关闭
+    pub udp_retries: u32,                   //UDP 超时重发次数, 只对 4E 帧有效
+    pub reconnect: Option<ReconnectPolicy>, //连接中断后自动重连, None 为不重连
+}
+
+impl Default for SlmpConfig {
+    fn default() -> SlmpConfig {
+        SlmpConfig {
+            connect_timeout: Duration::from_secs(2),
+            response_timeout: Duration::from_secs(2),
+            write_timeout: Duration::from_secs(2),
+            udp_retries: 2,
+            reconnect: None,
+        }
+    }
+}
+
+//自动重连策略
+//每次重连失败后等待时间翻倍, 直到 max_delay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,   //第一次重连前的等待时间
+    pub max_delay: Duration,       //最大等待时间
+    pub max_attempts: Option<u32>, //最大重连次数, None 为不限
+    pub jitter: bool,              //等待时间在 50%~100% 之间随机, 避免多个客户端同时重连
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_attempts: None,
+            jitter: true,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    //第 attempt 次重连(从 0 开始)前的等待时间
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        let d = self
+            .initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay);
+        if !self.jitter {
+            return d;
+        }
+        //RandomState 每次创建使用不同的随机密钥, 不需要引入随机数库
+        let r = RandomState::new().build_hasher().finish();
+        let frac = (r >> 11) as f64 / (1u64 << 53) as f64;
+        d.mul_f64(0.5 + 0.5 * frac)
+    }
+}
+
+//请求失败后的重试策略
+//读取请求默认重试, 写入请求只在 retry_writes 为 true 时重试
+//例如写 Y 输出时, 请求可能已经执行, 重复写入可能有危险
+//连接断开后只在设置了重连策略时于重连后重试, 没有重连策略时立即返回错误
+//3E帧超时后连接会断开, 所以超时只在 4E 帧或设置了重连策略时重试
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub count: u32,                //最大重试次数
+    pub delay: Duration,           //每次重试前的等待时间
+    pub retryable: Vec<ErrorKind>, //可以重试的错误类型
+    pub retry_writes: bool,        //写入请求也重试
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            count: 2,
+            delay: Duration::from_millis(100),
+            retryable: vec![ErrorKind::Timeout, ErrorKind::Disconnected],
+            retry_writes: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    //不重试
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            count: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    //第 attempt 次(从 0 开始)失败后是否重试
+    pub(crate) fn should_retry(&self, attempt: u32, idempotent: bool, e: &SlmpError) -> bool {
+        attempt < self.count
+            && (idempotent || self.retry_writes)
+            && self.retryable.contains(&e.kind())
+    }
+}
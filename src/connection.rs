@@ -0,0 +1,375 @@
+// 连接
+// 后台任务读取响应报文, 按序列号分发给等待的请求
+// 4E帧可以同时有多个请求等待响应, 3E帧同一时间只能有一个请求
+// 3E帧等待响应超时或请求被取消后关闭连接, 避免迟到的响应被当作下一个请求的响应
+// UDP 使用 4E 帧时, 超时未收到响应会用相同序列号重发请求
+use crate::config::SlmpConfig;
+use crate::error::SlmpError;
+use crate::slmp_core::{CpuSeries, DataCode, FrameType, Header};
+use crate::transport::Transport;
+use async_std::channel::{bounded, Sender};
+use async_std::task::JoinHandle;
+use async_std::{future::timeout, sync::Mutex as AsyncMutex};
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type Reply = Result<Vec<u8>, SlmpError>;
+type LostHook = Box<dyn FnOnce() + Send>;
+
+//连接和后台读任务共享的数据
+struct Shared {
+    pending: Mutex<HashMap<u16, Sender<Reply>>>, //等待响应的请求, 键为序列号(3E帧为0)
+    closed: AtomicBool,                          //连接已关闭, 后台读任务退出
+    lost: AtomicBool,                            //连接被对方关闭或通信出错而中断
+    on_lost: Mutex<Option<LostHook>>,            //连接中断时调用一次
+}
+
+impl Shared {
+    fn take(&self, serial: u16) -> Option<Sender<Reply>> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.remove(&serial)
+    }
+
+    //读任务出错时, 通知所有等待中的请求
+    fn fail_all<F: Fn() -> SlmpError>(&self, f: F) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for (_serial, tx) in pending.drain() {
+            let _ = tx.try_send(Err(f()));
+        }
+    }
+
+    //连接中断, 通知所有等待中的请求
+    //已经主动关闭的连接不算中断
+    fn lose<F: Fn() -> SlmpError>(&self, f: F) {
+        if !self.closed.load(Ordering::Acquire) {
+            self.lost.store(true, Ordering::Release);
+            self.closed.store(true, Ordering::Release);
+            let hook = self
+                .on_lost
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take();
+            if let Some(hook) = hook {
+                hook();
+            }
+        }
+        self.fail_all(f);
+    }
+}
+
+//连接状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    NotConnected, //未连接, 或已调用 shutdown
+    Connected,    //已连接
+    Disconnected, //连接被对方关闭或通信出错而中断
+    Reconnecting, //连接中断后正在自动重连
+}
+
+pub(crate) struct Connection {
+    transport: Arc<dyn Transport>,
+    frame: FrameType,
+    code: DataCode,
+    series: CpuSeries,
+    config: SlmpConfig,
+    serial: AtomicU16,      //下一个请求的序列号(4E帧)
+    single: AsyncMutex<()>, //3E帧同一时间只能有一个请求
+    shared: Arc<Shared>,
+    reader: Mutex<Option<JoinHandle<()>>>, //后台读任务
+    monitor: AsyncMutex<u64>,              //已登录监视的 Monitor, 0 为未登录
+}
+
+impl Connection {
+    pub(crate) fn new(
+        transport: Arc<dyn Transport>,
+        frame: FrameType,
+        code: DataCode,
+        series: CpuSeries,
+        config: SlmpConfig,
+    ) -> Connection {
+        let shared = Arc::new(Shared {
+            pending: Mutex::new(HashMap::new()),
+            closed: AtomicBool::new(false),
+            lost: AtomicBool::new(false),
+            on_lost: Mutex::new(None),
+        });
+        let reader =
+            async_std::task::spawn(read_loop(transport.clone(), frame, code, shared.clone()));
+        Connection {
+            transport,
+            frame,
+            code,
+            series,
+            config,
+            serial: AtomicU16::new(0),
+            single: AsyncMutex::new(()),
+            shared,
+            reader: Mutex::new(Some(reader)),
+            monitor: AsyncMutex::new(0),
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+
+    // 设置连接中断时的回调, 主动关闭连接时不调用
+    // 设置前已经中断时立即调用
+    pub(crate) fn set_on_lost<F: FnOnce() + Send + 'static>(&self, f: F) {
+        {
+            let mut hook = self
+                .shared
+                .on_lost
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            if !self.is_lost() {
+                *hook = Some(Box::new(f));
+                return;
+            }
+        }
+        f();
+    }
+
+    pub(crate) fn is_lost(&self) -> bool {
+        self.shared.lost.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn close(&self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.transport.close();
+        //UDP 的读任务不会因关闭而返回, 直接取消
+        let reader = self.reader.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(r) = reader {
+            async_std::task::spawn(r.cancel());
+        }
+    }
+
+    // 监视登录属于连接, 同一时间只有一个 Monitor 有效
+    // 登录和执行监视期间持有此锁
+    pub(crate) fn monitor(&self) -> &AsyncMutex<u64> {
+        &self.monitor
+    }
+
+    // 生成下一个请求的报文头
+    pub(crate) fn header(&self) -> Header {
+        let serial = match self.frame {
+            FrameType::Frame3E => 0,
+            FrameType::Frame4E => self.serial.fetch_add(1, Ordering::Relaxed),
+        };
+        Header::new(self.frame, self.code, serial).with_series(self.series)
+    }
+
+    // 发送请求报文并等待对应的响应报文
+    pub(crate) async fn request(&self, head: &Header, msg: &[u8]) -> Result<Vec<u8>, SlmpError> {
+        self.exchange(head, msg, true).await.0
+    }
+
+    // 只发送一次请求报文, UDP 时超时也不重发
+    pub(crate) async fn request_once(
+        &self,
+        head: &Header,
+        msg: &[u8],
+    ) -> Result<Vec<u8>, SlmpError> {
+        self.exchange(head, msg, false).await.0
+    }
+
+    // 发送请求报文, 用于 PLC 可能不响应的请求(远程 RESET)
+    // 请求已发出后等待响应超时或连接被对方关闭时返回 Ok(None)
+    // 请求没有发出时(写入失败等)返回错误
+    pub(crate) async fn request_no_reply(
+        &self,
+        head: &Header,
+        msg: &[u8],
+    ) -> Result<Option<Vec<u8>>, SlmpError> {
+        match self.exchange(head, msg, true).await {
+            (Ok(reply), _) => Ok(Some(reply)),
+            (Err(SlmpError::Timeout), true) | (Err(SlmpError::Disconnected), true) => Ok(None),
+            (Err(e), _) => Err(e),
+        }
+    }
+
+    // 返回响应和请求是否已发出
+    async fn exchange(
+        &self,
+        head: &Header,
+        msg: &[u8],
+        retransmit: bool,
+    ) -> (Result<Vec<u8>, SlmpError>, bool) {
+        let _single = match self.frame {
+            FrameType::Frame3E => Some(self.single.lock().await),
+            FrameType::Frame4E => None,
+        };
+        //3E帧等待前一个请求期间, 连接可能已被超时的请求关闭
+        if self.is_lost() {
+            return (Err(SlmpError::Disconnected), false);
+        }
+        if self.is_closed() {
+            return (Err(SlmpError::NotConnected), false);
+        }
+
+        let serial = head.serial();
+        let (tx, rx) = bounded(1);
+        {
+            let mut pending = self
+                .shared
+                .pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            pending.insert(serial, tx);
+        }
+        //请求结束或被取消时移除等待中的请求
+        let mut guard = PendingGuard {
+            conn: self,
+            serial,
+            sent: false,
+            replied: false,
+        };
+
+        //3E帧没有序列号, 重发后迟到的响应无法与下一个请求区分, 所以不重发
+        let attempts = match self.frame {
+            FrameType::Frame4E if retransmit && self.transport.is_datagram() => {
+                self.config.udp_retries + 1
+            }
+            _ => 1,
+        };
+        //总超时平均分给每次发送, 最后一次等待到总超时为止
+        let deadline = self.config.response_timeout;
+        let start = Instant::now();
+        let r = async {
+            for i in 0..attempts {
+                self.send(msg).await?;
+                guard.sent = true;
+                let wait = if i + 1 == attempts {
+                    deadline.saturating_sub(start.elapsed())
+                } else {
+                    deadline / attempts
+                };
+                match timeout(wait, rx.recv()).await {
+                    Ok(r) => {
+                        guard.replied = true;
+                        return r.unwrap_or(Err(SlmpError::NotConnected));
+                    }
+                    Err(_) => continue,
+                }
+            }
+            Err(SlmpError::Timeout)
+        }
+        .await;
+        let sent = guard.sent;
+        drop(guard);
+        (r, sent)
+    }
+
+    // 写入请求报文
+    async fn send(&self, msg: &[u8]) -> Result<(), SlmpError> {
+        let e = match timeout(self.config.write_timeout, self.transport.send(msg)).await {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(e)) if self.transport.is_datagram() => return Err(e.into()),
+            Ok(Err(e)) => SlmpError::from(e),
+            //报文可能只写入了一部分, 之后的报文无法被正确解析
+            Err(_) => SlmpError::Timeout,
+        };
+        //TCP 写入失败后连接无法继续使用
+        self.shared.lose(|| SlmpError::Disconnected);
+        self.close();
+        Err(e)
+    }
+}
+
+//等待响应的请求, 请求结束或 future 被丢弃时移除
+struct PendingGuard<'a> {
+    conn: &'a Connection,
+    serial: u16,
+    sent: bool,    //请求已发送
+    replied: bool, //已收到响应或错误通知
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.conn.shared.take(self.serial);
+        //3E帧没有序列号, 已发送但没有收到响应时(超时或请求被取消),
+        //迟到的响应会被当作下一个请求的响应, 连接无法继续使用
+        if self.conn.frame == FrameType::Frame3E && self.sent && !self.replied {
+            self.conn.shared.lose(|| SlmpError::Disconnected);
+            self.conn.close();
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// 后台读任务
+// 从连接中切分出完整的响应报文, 交给序列号相同的请求
+// 数据报传输时每个数据报单独切分, 不完整或结构不正确的数据报直接丢弃
+async fn read_loop(
+    transport: Arc<dyn Transport>,
+    frame: FrameType,
+    code: DataCode,
+    shared: Arc<Shared>,
+) {
+    let head = Header::new(frame, code, 0);
+    let datagram = transport.is_datagram();
+    let mut buffer: Vec<u8> = Vec::with_capacity(256);
+    //UDP 需要一次收下整个数据报
+    let mut b = vec![0u8; if datagram { 8192 } else { 256 }];
+
+    while !shared.closed.load(Ordering::Acquire) {
+        let n = match transport.recv(&mut b).await {
+            //空数据报
+            Ok(0) if datagram => continue,
+            //对方关闭了连接
+            Ok(0) => {
+                shared.lose(|| SlmpError::Disconnected);
+                break;
+            }
+            Ok(n) => n,
+            //对方端口未打开时 UDP 会收到 ICMP 错误, 等待重发即可
+            Err(_) if datagram => {
+                async_std::task::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+            Err(e) => {
+                shared.lose(|| SlmpError::from(io::Error::new(e.kind(), e.to_string())));
+                break;
+            }
+        };
+        if datagram {
+            buffer.clear();
+        }
+        buffer.extend_from_slice(&b[..n]);
+
+        loop {
+            let len = match head.frame_len(&buffer) {
+                Ok(Some(len)) => len,
+                //报文不完整
+                Ok(None) => break,
+                //数据报直接丢弃
+                Err(_) if datagram => {
+                    buffer.clear();
+                    break;
+                }
+                //字节流无法再切分后续报文, 连接无法继续使用
+                Err(_) => {
+                    buffer.clear();
+                    shared.lose(|| SlmpError::MalformedFrame);
+                    transport.close();
+                    break;
+                }
+            };
+            let reply: Vec<u8> = buffer.drain(..len).collect();
+            let serial = head.serial_of(&reply).unwrap_or(0);
+            //没有对应的请求时(已超时), 丢弃响应
+            if let Some(tx) = shared.take(serial) {
+                let _ = tx.try_send(Ok(reply));
+            }
+        }
+    }
+    shared.closed.store(true, Ordering::Release);
+}
@@ -0,0 +1,295 @@
+use std::fmt;
+use std::io;
+
+//通信错误
+#[derive(Debug)]
+pub enum SlmpError {
+    Io(io::Error),       //读写连接时的IO错误
+    Timeout,             //等待响应超时
+    NotConnected,        //未建立连接
+    MalformedFrame,      //响应报文结构不正确
+    DestinationMismatch, //响应的目标地址与请求不一致
+    PlcEndCode {
+        code: PlcErrorCode,            //结束代码
+        error_info: Option<ErrorInfo>, //错误信息
+    }, //PLC返回了非零结束代码
+    InvalidRequest(String), //请求参数不正确,请求未发送
+    Disconnected,        //连接已被对方关闭或中断, 需要重新连接
+    ModelMismatch {
+        expected: String, //期望的型号
+        actual: String,   //PLC返回的型号
+    }, //连接的PLC型号与期望的不一致
+}
+
+//错误类型, 用于重试策略等按类型判断错误的场合
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Io,
+    Timeout,
+    NotConnected,
+    MalformedFrame,
+    DestinationMismatch,
+    PlcEndCode,
+    InvalidRequest,
+    Disconnected,
+    ModelMismatch,
+}
+
+impl SlmpError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            SlmpError::Io(_) => ErrorKind::Io,
+            SlmpError::Timeout => ErrorKind::Timeout,
+            SlmpError::NotConnected => ErrorKind::NotConnected,
+            SlmpError::MalformedFrame => ErrorKind::MalformedFrame,
+            SlmpError::DestinationMismatch => ErrorKind::DestinationMismatch,
+            SlmpError::PlcEndCode { .. } => ErrorKind::PlcEndCode,
+            SlmpError::InvalidRequest(_) => ErrorKind::InvalidRequest,
+            SlmpError::Disconnected => ErrorKind::Disconnected,
+            SlmpError::ModelMismatch { .. } => ErrorKind::ModelMismatch,
+        }
+    }
+}
+
+impl fmt::Display for SlmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlmpError::Io(e) => write!(f, "io error: {}", e),
+            SlmpError::Timeout => write!(f, "timed out waiting for response"),
+            SlmpError::NotConnected => write!(f, "not connected"),
+            SlmpError::MalformedFrame => write!(f, "malformed response frame"),
+            SlmpError::DestinationMismatch => {
+                write!(f, "response destination does not match request")
+            }
+            SlmpError::PlcEndCode { code, error_info } => {
+                write!(f, "plc error {}", code)?;
+                if let Some(info) = error_info {
+                    write!(f, " ({})", info)?;
+                }
+                Ok(())
+            }
+            SlmpError::InvalidRequest(msg) => write!(f, "invalid request: {}", msg),
+            SlmpError::Disconnected => write!(f, "connection closed by peer"),
+            SlmpError::ModelMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "cpu model mismatch: expected {}, got {}",
+                    expected, actual
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SlmpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SlmpError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SlmpError {
+    fn from(e: io::Error) -> SlmpError {
+        match e.kind() {
+            //async_std::io::timeout 超时返回 TimedOut
+            io::ErrorKind::TimedOut => SlmpError::Timeout,
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted => SlmpError::Disconnected,
+            _ => SlmpError::Io(e),
+        }
+    }
+}
+
+//PLC返回的结束代码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlcErrorCode {
+    AsciiConversion,            //0xC050
+    BitPointsOutOfRange,        //0xC051
+    WordPointsOutOfRange,       //0xC052
+    RandomBitPointsOutOfRange,  //0xC053
+    RandomWordPointsOutOfRange, //0xC054
+    DeviceOutOfRange,           //0xC056
+    DataLengthMismatch,         //0xC058
+    UnsupportedCommand,         //0xC059
+    DeviceNotAccessible,        //0xC05B
+    InvalidRequestContent,      //0xC05C
+    RequestNotExecutable,       //0xC05F
+    RequestLengthMismatch,      //0xC061
+    DataCodeMismatch,           //0xC06F
+    RemotePasswordMismatch,     //0xC200
+    RemotePasswordLocked,       //0xC201
+    RemotePasswordOtherDevice,  //0xC204
+    DeviceNotSupported,         //0x4030
+    DeviceRangeExceeded,        //0x4031
+    Other(u16),                 //其它结束代码
+}
+
+impl PlcErrorCode {
+    pub fn from_code(code: u16) -> PlcErrorCode {
+        match code {
+            0xC050 => PlcErrorCode::AsciiConversion,
+            0xC051 => PlcErrorCode::BitPointsOutOfRange,
+            0xC052 => PlcErrorCode::WordPointsOutOfRange,
+            0xC053 => PlcErrorCode::RandomBitPointsOutOfRange,
+            0xC054 => PlcErrorCode::RandomWordPointsOutOfRange,
+            0xC056 => PlcErrorCode::DeviceOutOfRange,
+            0xC058 => PlcErrorCode::DataLengthMismatch,
+            0xC059 => PlcErrorCode::UnsupportedCommand,
+            0xC05B => PlcErrorCode::DeviceNotAccessible,
+            0xC05C => PlcErrorCode::InvalidRequestContent,
+            0xC05F => PlcErrorCode::RequestNotExecutable,
+            0xC061 => PlcErrorCode::RequestLengthMismatch,
+            0xC06F => PlcErrorCode::DataCodeMismatch,
+            0xC200 => PlcErrorCode::RemotePasswordMismatch,
+            0xC201 => PlcErrorCode::RemotePasswordLocked,
+            0xC204 => PlcErrorCode::RemotePasswordOtherDevice,
+            0x4030 => PlcErrorCode::DeviceNotSupported,
+            0x4031 => PlcErrorCode::DeviceRangeExceeded,
+            c => PlcErrorCode::Other(c),
+        }
+    }
+
+    //结束代码原值
+    pub fn code(&self) -> u16 {
+        match self {
+            PlcErrorCode::AsciiConversion => 0xC050,
+            PlcErrorCode::BitPointsOutOfRange => 0xC051,
+            PlcErrorCode::WordPointsOutOfRange => 0xC052,
+            PlcErrorCode::RandomBitPointsOutOfRange => 0xC053,
+            PlcErrorCode::RandomWordPointsOutOfRange => 0xC054,
+            PlcErrorCode::DeviceOutOfRange => 0xC056,
+            PlcErrorCode::DataLengthMismatch => 0xC058,
+            PlcErrorCode::UnsupportedCommand => 0xC059,
+            PlcErrorCode::DeviceNotAccessible => 0xC05B,
+            PlcErrorCode::InvalidRequestContent => 0xC05C,
+            PlcErrorCode::RequestNotExecutable => 0xC05F,
+            PlcErrorCode::RequestLengthMismatch => 0xC061,
+            PlcErrorCode::DataCodeMismatch => 0xC06F,
+            PlcErrorCode::RemotePasswordMismatch => 0xC200,
+            PlcErrorCode::RemotePasswordLocked => 0xC201,
+            PlcErrorCode::RemotePasswordOtherDevice => 0xC204,
+            PlcErrorCode::DeviceNotSupported => 0x4030,
+            PlcErrorCode::DeviceRangeExceeded => 0x4031,
+            PlcErrorCode::Other(c) => *c,
+        }
+    }
+
+    //名称, 未知结束代码返回 "Unknown"
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlcErrorCode::AsciiConversion => "AsciiConversion",
+            PlcErrorCode::BitPointsOutOfRange => "BitPointsOutOfRange",
+            PlcErrorCode::WordPointsOutOfRange => "WordPointsOutOfRange",
+            PlcErrorCode::RandomBitPointsOutOfRange => "RandomBitPointsOutOfRange",
+            PlcErrorCode::RandomWordPointsOutOfRange => "RandomWordPointsOutOfRange",
+            PlcErrorCode::DeviceOutOfRange => "DeviceOutOfRange",
+            PlcErrorCode::DataLengthMismatch => "DataLengthMismatch",
+            PlcErrorCode::UnsupportedCommand => "UnsupportedCommand",
+            PlcErrorCode::DeviceNotAccessible => "DeviceNotAccessible",
+            PlcErrorCode::InvalidRequestContent => "InvalidRequestContent",
+            PlcErrorCode::RequestNotExecutable => "RequestNotExecutable",
+            PlcErrorCode::RequestLengthMismatch => "RequestLengthMismatch",
+            PlcErrorCode::DataCodeMismatch => "DataCodeMismatch",
+            PlcErrorCode::RemotePasswordMismatch => "RemotePasswordMismatch",
+            PlcErrorCode::RemotePasswordLocked => "RemotePasswordLocked",
+            PlcErrorCode::RemotePasswordOtherDevice => "RemotePasswordOtherDevice",
+            PlcErrorCode::DeviceNotSupported => "DeviceNotSupported",
+            PlcErrorCode::DeviceRangeExceeded => "DeviceRangeExceeded",
+            PlcErrorCode::Other(_) => "Unknown",
+        }
+    }
+
+    //说明
+    pub fn description(&self) -> &'static str {
+        match self {
+            PlcErrorCode::AsciiConversion => {
+                "ASCII code data that cannot be converted to binary was received"
+            }
+            PlcErrorCode::BitPointsOutOfRange => {
+                "number of bit device points to read/write is out of range"
+            }
+            PlcErrorCode::WordPointsOutOfRange => {
+                "number of word device points to read/write is out of range"
+            }
+            PlcErrorCode::RandomBitPointsOutOfRange => {
+                "number of bit device points for random read/write is out of range"
+            }
+            PlcErrorCode::RandomWordPointsOutOfRange => {
+                "number of word device points for random read/write is out of range"
+            }
+            PlcErrorCode::DeviceOutOfRange => {
+                "read/write request exceeds the maximum device address"
+            }
+            PlcErrorCode::DataLengthMismatch => {
+                "request data length does not match the number of points"
+            }
+            PlcErrorCode::UnsupportedCommand => {
+                "command or subcommand is not supported by the target"
+            }
+            PlcErrorCode::DeviceNotAccessible => "the target cannot read/write the device",
+            PlcErrorCode::InvalidRequestContent => "request content is invalid",
+            PlcErrorCode::RequestNotExecutable => "request cannot be executed by the target",
+            PlcErrorCode::RequestLengthMismatch => {
+                "request data length does not match the data actually sent"
+            }
+            PlcErrorCode::DataCodeMismatch => {
+                "communication data code (binary/ASCII) does not match the target setting"
+            }
+            PlcErrorCode::RemotePasswordMismatch => "remote password is incorrect",
+            PlcErrorCode::RemotePasswordLocked => "port is locked by remote password",
+            PlcErrorCode::RemotePasswordOtherDevice => "port was unlocked by a different device",
+            PlcErrorCode::DeviceNotSupported => "the device is not supported by the CPU",
+            PlcErrorCode::DeviceRangeExceeded => "the device number is outside the CPU range",
+            PlcErrorCode::Other(_) => "unknown end code",
+        }
+    }
+}
+
+impl From<u16> for PlcErrorCode {
+    fn from(code: u16) -> PlcErrorCode {
+        PlcErrorCode::from_code(code)
+    }
+}
+
+impl fmt::Display for PlcErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:04X} {}: {}",
+            self.code(),
+            self.name(),
+            self.description()
+        )
+    }
+}
+
+//结束代码之后的错误信息
+//发生错误的站和被拒绝的指令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorInfo {
+    pub network: u8,           //网络编号
+    pub station: u8,           //站号
+    pub module: u16,           //模块IO编号
+    pub multidrop_station: u8, //多点站号
+    pub command: u16,          //指令
+    pub subcommand: u16,       //子指令
+}
+
+impl fmt::Display for ErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "network {} station 0x{:02X} module 0x{:04X} multidrop {}, command 0x{:04X} subcommand 0x{:04X}",
+            self.network,
+            self.station,
+            self.module,
+            self.multidrop_station,
+            self.command,
+            self.subcommand
+        )
+    }
+}
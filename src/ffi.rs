@@ -0,0 +1,281 @@
+// C 接口, 声明见 include/slmp.h
+// 句柄是连接表中的编号,不是真实指针
+// 所以 slmp_shutdown 可以重复调用, 失效的句柄只会返回错误
+use crate::{DeviceBit, DeviceWord, Slmp, SlmpError};
+use async_std::task::block_on;
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr};
+use std::net::{IpAddr, SocketAddr};
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+//返回值
+const OK: i32 = 0;
+const ERR_NOT_CONNECTED: i32 = -1; //句柄无效或未连接
+const ERR_IO: i32 = -2; //通信错误
+const ERR_TIMEOUT: i32 = -3; //等待响应超时
+const ERR_MALFORMED_FRAME: i32 = -4; //响应报文结构不正确
+const ERR_DESTINATION_MISMATCH: i32 = -5; //响应的目标地址与请求不一致
+const ERR_INVALID_REQUEST: i32 = -6; //请求参数不正确
+const ERR_DISCONNECTED: i32 = -7; //连接已被对方关闭或中断
+const ERR_MODEL_MISMATCH: i32 = -8; //PLC型号与期望的不一致
+
+type Handles = Mutex<HashMap<usize, Slmp>>;
+
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(1);
+
+fn handles() -> &'static Handles {
+    static HANDLES: OnceLock<Handles> = OnceLock::new();
+    HANDLES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn get(slmp: *mut c_void) -> Option<Slmp> {
+    let map = handles().lock().unwrap_or_else(|e| e.into_inner());
+    map.get(&(slmp as usize)).cloned()
+}
+
+//PLC返回的结束代码原样返回(正数), 其它错误返回负数
+fn error_code(e: &SlmpError) -> i32 {
+    match e {
+        SlmpError::Io(_) => ERR_IO,
+        SlmpError::Timeout => ERR_TIMEOUT,
+        SlmpError::NotConnected => ERR_NOT_CONNECTED,
+        SlmpError::MalformedFrame => ERR_MALFORMED_FRAME,
+        SlmpError::DestinationMismatch => ERR_DESTINATION_MISMATCH,
+        SlmpError::PlcEndCode { code, .. } => code.code() as i32,
+        SlmpError::InvalidRequest(_) => ERR_INVALID_REQUEST,
+        SlmpError::Disconnected => ERR_DISCONNECTED,
+        SlmpError::ModelMismatch { .. } => ERR_MODEL_MISMATCH,
+    }
+}
+
+fn result_code(r: Result<(), SlmpError>) -> i32 {
+    match r {
+        Ok(()) => OK,
+        Err(e) => error_code(&e),
+    }
+}
+
+fn device_word(dev: u16) -> Option<DeviceWord> {
+    match dev {
+        1 => Some(DeviceWord::D),
+        2 => Some(DeviceWord::R),
+        3 => Some(DeviceWord::ZR),
+        4 => Some(DeviceWord::W),
+        5 => Some(DeviceWord::SD),
+        6 => Some(DeviceWord::SW),
+        7 => Some(DeviceWord::TN),
+        8 => Some(DeviceWord::CN),
+        9 => Some(DeviceWord::STN),
+        10 => Some(DeviceWord::Z),
+        _ => None,
+    }
+}
+
+fn device_bit(dev: u16) -> Option<DeviceBit> {
+    match dev {
+        1 => Some(DeviceBit::M),
+        2 => Some(DeviceBit::X),
+        3 => Some(DeviceBit::Y),
+        4 => Some(DeviceBit::B),
+        5 => Some(DeviceBit::L),
+        6 => Some(DeviceBit::F),
+        7 => Some(DeviceBit::V),
+        8 => Some(DeviceBit::SM),
+        9 => Some(DeviceBit::SB),
+        10 => Some(DeviceBit::DX),
+        11 => Some(DeviceBit::DY),
+        12 => Some(DeviceBit::TS),
+        13 => Some(DeviceBit::TC),
+        14 => Some(DeviceBit::CS),
+        15 => Some(DeviceBit::CC),
+        16 => Some(DeviceBit::STS),
+        17 => Some(DeviceBit::STC),
+        _ => None,
+    }
+}
+
+// 连接PLC
+// 连接失败返回 null
+//
+// # Safety
+// ip 必须是以 '\0' 结尾的字符串
+#[no_mangle]
+pub unsafe extern "C" fn slmp_connect(ip: *const c_char, port: u16) -> *mut c_void {
+    if ip.is_null() {
+        return std::ptr::null_mut();
+    }
+    let ip: IpAddr = match CStr::from_ptr(ip)
+        .to_str()
+        .ok()
+        .and_then(|s| s.parse().ok())
+    {
+        Some(ip) => ip,
+        None => return std::ptr::null_mut(),
+    };
+    let addr = SocketAddr::new(ip, port);
+    let slmp = Slmp::new();
+    if block_on(slmp.connect(&addr)).is_err() {
+        return std::ptr::null_mut();
+    }
+    let id = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    let mut map = handles().lock().unwrap_or_else(|e| e.into_inner());
+    map.insert(id, slmp);
+    id as *mut c_void
+}
+
+// 断开连接并释放资源
+// slmp 为 null 或已释放时不做任何操作
+#[no_mangle]
+pub extern "C" fn slmp_shutdown(slmp: *mut c_void) {
+    let removed = {
+        let mut map = handles().lock().unwrap_or_else(|e| e.into_inner());
+        map.remove(&(slmp as usize))
+    };
+    if let Some(s) = removed {
+        let _ = s.shutdown();
+    }
+}
+
+// 批量读取字软元件
+// 执行成功返回 0
+//
+// # Safety
+// data 必须指向至少 number 个 uint16_t
+#[no_mangle]
+pub unsafe extern "C" fn slmp_read_words(
+    slmp: *mut c_void,
+    head_number: u32,
+    dev: u16,
+    number: u16,
+    data: *mut u16,
+) -> i32 {
+    let s = match get(slmp) {
+        Some(s) => s,
+        None => return ERR_NOT_CONNECTED,
+    };
+    let dev = match device_word(dev) {
+        Some(d) => d,
+        None => return ERR_INVALID_REQUEST,
+    };
+    if data.is_null() {
+        return ERR_INVALID_REQUEST;
+    }
+    match block_on(s.read_words(head_number, dev, number)) {
+        Ok(v) => {
+            let out = std::slice::from_raw_parts_mut(data, number as usize);
+            out.copy_from_slice(&v);
+            OK
+        }
+        Err(e) => error_code(&e),
+    }
+}
+
+// 批量读取位软元件
+// 执行成功返回 0, data 中每个元素为 0 或 1
+//
+// # Safety
+// data 必须指向至少 number 个 uint8_t
+#[no_mangle]
+pub unsafe extern "C" fn slmp_read_bits(
+    slmp: *mut c_void,
+    head_number: u32,
+    dev: u16,
+    number: u16,
+    data: *mut u8,
+) -> i32 {
+    let s = match get(slmp) {
+        Some(s) => s,
+        None => return ERR_NOT_CONNECTED,
+    };
+    let dev = match device_bit(dev) {
+        Some(d) => d,
+        None => return ERR_INVALID_REQUEST,
+    };
+    if data.is_null() {
+        return ERR_INVALID_REQUEST;
+    }
+    match block_on(s.read_bits(head_number, dev, number)) {
+        Ok(v) => {
+            let out = std::slice::from_raw_parts_mut(data, number as usize);
+            for (o, b) in out.iter_mut().zip(v) {
+                *o = b as u8;
+            }
+            OK
+        }
+        Err(e) => error_code(&e),
+    }
+}
+
+// 批量写入字软元件
+// 执行成功返回 0
+//
+// # Safety
+// data 必须指向至少 number 个 uint16_t
+#[no_mangle]
+pub unsafe extern "C" fn slmp_write_words(
+    slmp: *mut c_void,
+    head_number: u32,
+    dev: u16,
+    number: u16,
+    data: *const u16,
+) -> i32 {
+    let s = match get(slmp) {
+        Some(s) => s,
+        None => return ERR_NOT_CONNECTED,
+    };
+    let dev = match device_word(dev) {
+        Some(d) => d,
+        None => return ERR_INVALID_REQUEST,
+    };
+    if data.is_null() {
+        return ERR_INVALID_REQUEST;
+    }
+    let d = std::slice::from_raw_parts(data, number as usize);
+    result_code(block_on(s.write_words(head_number, dev, d)))
+}
+
+// 批量写入位软元件
+// 执行成功返回 0, data 中非零元素为 ON
+//
+// # Safety
+// data 必须指向至少 number 个 uint8_t
+#[no_mangle]
+pub unsafe extern "C" fn slmp_write_bits(
+    slmp: *mut c_void,
+    head_number: u32,
+    dev: u16,
+    number: u16,
+    data: *const u8,
+) -> i32 {
+    let s = match get(slmp) {
+        Some(s) => s,
+        None => return ERR_NOT_CONNECTED,
+    };
+    let dev = match device_bit(dev) {
+        Some(d) => d,
+        None => return ERR_INVALID_REQUEST,
+    };
+    if data.is_null() {
+        return ERR_INVALID_REQUEST;
+    }
+    let d: Vec<bool> = std::slice::from_raw_parts(data, number as usize)
+        .iter()
+        .map(|&b| b != 0)
+        .collect();
+    result_code(block_on(s.write_bits(head_number, dev, &d)))
+}
+
+#[test]
+fn test_ffi_invalid_handle() {
+    let invalid = 0x7fff_ffff as *mut c_void;
+    slmp_shutdown(std::ptr::null_mut());
+    slmp_shutdown(invalid);
+    slmp_shutdown(invalid);
+    let mut data = [0u16; 4];
+    let r = unsafe { slmp_read_words(invalid, 0, 1, 4, data.as_mut_ptr()) };
+    assert_eq!(r, ERR_NOT_CONNECTED);
+    let r = unsafe { slmp_connect(std::ptr::null(), 5000) };
+    assert!(r.is_null());
+}
@@ -1,151 +1,707 @@
-use async_std::{io::timeout, net::TcpStream};
-use std::net::{Shutdown, SocketAddr};
-use std::time::{Duration, Instant};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(test)]
+use std::time::Instant;
 
+mod address;
+mod config;
+mod connection;
+mod error;
+mod ffi;
+mod link;
+mod monitor;
 mod slmp_core;
-use crate::slmp_core::{read_bits, read_blocks, read_words, write_bits, write_blocks, write_words};
-pub use crate::slmp_core::{DeviceBit, DeviceWord};
+mod transport;
+pub use crate::address::{DeviceAddress, DeviceType};
+pub use crate::config::{ReconnectPolicy, RetryPolicy, SlmpConfig};
+use crate::connection::Connection;
+pub use crate::connection::ConnectionState;
+pub use crate::error::{ErrorInfo, ErrorKind, PlcErrorCode, SlmpError};
+use crate::link::{Link, Params};
+pub use crate::monitor::Monitor;
+use crate::slmp_core::{
+    lock, loopback, ping, read_bits, read_blocks, read_random, read_type_name, read_words,
+    remote_latch_clear, remote_pause, remote_reset, remote_run, remote_stop, unlock, write_bits,
+    write_blocks, write_random_bits, write_random_words, write_words, Header,
+};
+pub use crate::slmp_core::{
+    Blocks, ClearMode, CpuInfo, CpuSeries, DataCode, Destination, DeviceBit, DeviceWord, FrameType,
+    MonitorValues, RandomValues,
+};
+pub use crate::transport::TransportType;
 
+// 克隆得到的 Slmp 共用同一个连接, 可以在多个任务中同时发送请求
+// 使用 4E 帧时多个请求可以同时等待响应, 3E 帧时请求依次执行
+// 响应的目标地址与请求不一致时返回 Err(SlmpError::DestinationMismatch)
+#[derive(Clone)]
 pub struct Slmp {
-    stream: Vec<TcpStream>,
+    frame: FrameType,         //报文格式
+    code: DataCode,           //通信数据代码
+    series: CpuSeries,        //CPU 系列
+    transport: TransportType, //传输方式
+    config: SlmpConfig,       //连接配置
+    des: Destination,         //请求目标地址
+    timer: Duration,          //监视定时器
+    retry: RetryPolicy,       //重试策略
+    model: Option<String>,    //connect 时检查的 CPU 型号
+    password: Option<String>, //连接后自动解锁的远程密码
+    link: Arc<Link>,          //连接
+}
+
+impl Default for Slmp {
+    fn default() -> Slmp {
+        Slmp::new()
+    }
 }
 
 impl Slmp {
     pub fn new() -> Slmp {
         Slmp {
-            stream: Vec::with_capacity(1),
+            frame: FrameType::Frame3E,
+            code: DataCode::Binary,
+            series: CpuSeries::QL,
+            transport: TransportType::Tcp,
+            config: SlmpConfig::default(),
+            des: Destination::new(),
+            timer: Duration::ZERO,
+            retry: RetryPolicy::default(),
+            model: None,
+            password: None,
+            link: Arc::new(Link::new()),
         }
     }
 
-    pub async fn connect(&mut self, addr: &SocketAddr) -> Result<(), ()> {
-        if !self.stream.is_empty() {
-            return Err(());
-        }
+    // 设置报文格式, 默认为 3E 帧
+    // 在 connect 之前设置, 对之后建立的连接有效
+    pub fn set_frame_type(&mut self, frame: FrameType) {
+        self.frame = frame;
+    }
 
-        let r = timeout(Duration::from_secs(2), async {
-            TcpStream::connect(addr).await
-        })
-        .await;
-        match r {
-            Ok(stream) => {
-                let _ = stream.set_nodelay(true);
-                self.stream.push(stream);
-                Ok(())
+    pub fn frame_type(&self) -> FrameType {
+        self.frame
+    }
+
+    // 设置通信数据代码, 默认为二进制
+    // 需要与PLC以太网端口的设置一致
+    // 在 connect 之前设置, 对之后建立的连接有效
+    pub fn set_data_code(&mut self, code: DataCode) {
+        self.code = code;
+    }
+
+    pub fn data_code(&self) -> DataCode {
+        self.code
+    }
+
+    // 设置 CPU 系列, 默认为 Q/L 系列
+    // iQ-R 系列使用子指令 0002/0003, 软元件编号为4字节, 可以访问 LTN, LCN, LZ, RD 等软元件
+    // 在 connect 之前设置, 对之后建立的连接有效
+    pub fn set_cpu_series(&mut self, series: CpuSeries) {
+        self.series = series;
+    }
+
+    pub fn cpu_series(&self) -> CpuSeries {
+        self.series
+    }
+
+    // 设置传输方式, 默认为 TCP
+    // UDP 需要同时设置 4E 帧, 数据报丢失时用序列号重发
+    // 在 connect 之前设置, 对之后建立的连接有效
+    pub fn set_transport_type(&mut self, transport: TransportType) {
+        self.transport = transport;
+    }
+
+    pub fn transport_type(&self) -> TransportType {
+        self.transport
+    }
+
+    // 设置超时, UDP 重发次数等连接配置
+    // 在 connect 之前设置, 对之后建立的连接有效
+    pub fn set_config(&mut self, config: SlmpConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> SlmpConfig {
+        self.config
+    }
+
+    // 设置请求失败后的重试策略
+    // 默认读取请求超时或连接中断时重试 2 次, 写入请求不重试
+    // 立即生效, 不影响共用连接的其它 Slmp
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    // 设置请求目标地址, 默认为直接连接的站
+    // 立即生效, 不影响共用连接的其它 Slmp
+    pub fn set_destination(&mut self, des: Destination) {
+        self.des = des;
+    }
+
+    pub fn destination(&self) -> Destination {
+        self.des
+    }
+
+    // 返回共用同一个连接, 目标地址不同的 Slmp, 用于单次请求
+    // 例: slmp.with_destination(Destination::new().module(Destination::MODULE_CPU2)).read_words(..)
+    pub fn with_destination(&self, des: Destination) -> Slmp {
+        let mut s = self.clone();
+        s.des = des;
+        s
+    }
+
+    // 设置监视定时器, PLC 等待请求处理完成的时间, 单位为 250ms, 不足 250ms 的部分进位
+    // 默认为 0, PLC 一直等待到处理完成
+    // 经由网络访问其它站时设置, 超时后 PLC 返回结束代码, 而不是一直等待
+    // 立即生效, 不影响共用连接的其它 Slmp
+    pub fn set_monitoring_timer(&mut self, timer: Duration) {
+        self.timer = timer;
+    }
+
+    pub fn monitoring_timer(&self) -> Duration {
+        self.timer
+    }
+
+    // 返回共用同一个连接, 监视定时器不同的 Slmp, 用于单次请求
+    pub fn with_monitoring_timer(&self, timer: Duration) -> Slmp {
+        let mut s = self.clone();
+        s.timer = timer;
+        s
+    }
+
+    // 设置期望的 CPU 型号, 默认为 None, 不检查
+    // connect 后读取型号, 与期望的不一致时断开连接, 返回 Err(SlmpError::ModelMismatch)
+    // 型号比较时忽略末尾的空格
+    pub fn set_expected_model(&mut self, model: Option<&str>) {
+        self.model = model.map(|m| m.trim_end().to_string());
+    }
+
+    pub fn expected_model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    // 设置远程密码, 默认为 None
+    // 设置后 connect 和自动重连建立连接时先解锁, 解锁失败时连接失败
+    // 在 connect 之前设置, 对之后建立的连接有效
+    pub fn set_password(&mut self, password: Option<&str>) {
+        self.password = password.map(|p| p.to_string());
+    }
+
+    // 生成请求报文头
+    fn header(&self, conn: &Connection) -> Header {
+        conn.header()
+            .with_destination(self.des)
+            .with_monitoring_timer(self.timer)
+    }
+
+    // 执行请求
+    // 设置了重连策略时, 连接中断后等待重连完成(最多等待响应超时时间)
+    // 只读请求在重连后重新发送一次, 写入请求可能已经执行, 不重新发送
+    // 之后按重试策略重试, 连接已断开时只在设置了重连策略时于重连后的新连接上重试
+    async fn run<T, F, Fut>(&self, idempotent: bool, f: F) -> Result<T, SlmpError>
+    where
+        F: Fn(Arc<Connection>, Header) -> Fut,
+        Fut: Future<Output = Result<T, SlmpError>>,
+    {
+        let wait = self.config.response_timeout;
+        let mut reconnected = false;
+        let mut attempt = 0;
+        loop {
+            let r = async {
+                let conn = self.link.connection(wait).await?;
+                let head = self.header(&conn);
+                f(conn, head).await
+            }
+            .await;
+            let e = match r {
+                Err(e) => e,
+                r => return r,
+            };
+            if idempotent
+                && !reconnected
+                && e.kind() == ErrorKind::Disconnected
+                && self.link.policy_enabled()
+            {
+                reconnected = true;
+                continue;
             }
-            Err(_e) => Err(()),
+            //连接已断开且没有重连策略时, 重试也只会得到 Disconnected
+            //3E帧超时后连接也会断开, 所以只有 4E 帧或设置了重连策略时超时才会重试
+            if !self.link.policy_enabled() && self.link.current().is_some_and(|c| c.is_lost()) {
+                return Err(e);
+            }
+            if !self.retry.should_retry(attempt, idempotent, &e) {
+                return Err(e);
+            }
+            attempt += 1;
+            async_std::task::sleep(self.retry.delay).await;
         }
     }
 
-    pub fn shutdown(&mut self) -> Result<(), ()> {
-        if let Some(stream) = self.stream.first_mut() {
-            let _ = stream.shutdown(Shutdown::Both);
+    // 连接PLC
+    // 已有连接时返回 Err(SlmpError::InvalidRequest)
+    // UDP 传输使用 3E 帧时返回 Err(SlmpError::InvalidRequest)
+    // 设置了 SlmpConfig::reconnect 时, 连接中断后自动重连到同一地址
+    pub async fn connect(&self, addr: &SocketAddr) -> Result<(), SlmpError> {
+        //3E帧没有序列号, 数据报丢失后无法重发, 一次超时就会断开连接
+        if self.transport == TransportType::Udp && self.frame == FrameType::Frame3E {
+            return Err(SlmpError::InvalidRequest(
+                "udp transport requires 4E frames".to_string(),
+            ));
+        }
+        let params = Params {
+            addr: *addr,
+            frame: self.frame,
+            code: self.code,
+            series: self.series,
+            transport: self.transport,
+            config: self.config,
+            password: self.password.clone(),
+        };
+        self.link.connect(params).await?;
+        if let Some(expected) = &self.model {
+            let r = match self.read_cpu_info().await {
+                Ok(info) if info.model == *expected => Ok(()),
+                Ok(info) => Err(SlmpError::ModelMismatch {
+                    expected: expected.clone(),
+                    actual: info.model,
+                }),
+                Err(e) => Err(e),
+            };
+            if r.is_err() {
+                let _ = self.shutdown();
+            }
+            return r;
         }
-        self.stream.clear();
+        Ok(())
+    }
+
+    // 连接状态
+    // 连接被对方关闭或通信出错后为 Disconnected, 请求返回 Err(SlmpError::Disconnected)
+    // 没有设置重连策略时, 需要调用 connect 重新连接
+    pub fn state(&self) -> ConnectionState {
+        self.link.state()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state() == ConnectionState::Connected
+    }
+
+    // 注册连接状态变化回调, 所有克隆的 Slmp 共用
+    // 回调在状态变化处同步调用: connect/shutdown 时在调用者的任务中,
+    // 连接中断时在后台读任务或发出请求的任务中, 自动重连时在后台重连任务中
+    // 回调阻塞时 connect/shutdown 和请求也会阻塞, 耗时的处理应交给其它任务
+    pub fn on_state_change<F>(&self, f: F)
+    where
+        F: Fn(ConnectionState) + Send + Sync + 'static,
+    {
+        self.link.add_listener(Arc::new(f));
+    }
+
+    // 断开连接, 所有克隆的 Slmp 都会断开, 并停止自动重连
+    pub fn shutdown(&self) -> Result<(), SlmpError> {
+        self.link.shutdown();
         Ok(())
     }
 
     // 批量读取字软元件
     // 读取成功返回 值数组
-    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-    // 其它错误都返回 Err(0)
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
     pub async fn read_words(
-        &mut self,
+        &self,
         head_number: u32,
         dev: DeviceWord,
         number: u16,
-    ) -> Result<Vec<u16>, u16> {
-        if let Some(stream) = self.stream.first_mut() {
-            return read_words(stream, dev, head_number, number).await;
-        }
-        Err(0)
+    ) -> Result<Vec<u16>, SlmpError> {
+        self.run(true, |conn, head| async move {
+            read_words(&conn, &head, dev, head_number, number).await
+        })
+        .await
     }
 
     // 批量读取位软元件
     // 读取成功返回 值数组
-    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-    // 其它错误都返回 Err(0)
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
     pub async fn read_bits(
-        &mut self,
+        &self,
         head_number: u32,
         dev: DeviceBit,
         number: u16,
-    ) -> Result<Vec<bool>, u16> {
-        if let Some(stream) = self.stream.first_mut() {
-            return read_bits(stream, dev, head_number, number).await;
-        }
-        Err(0)
+    ) -> Result<Vec<bool>, SlmpError> {
+        self.run(true, |conn, head| async move {
+            read_bits(&conn, &head, dev, head_number, number).await
+        })
+        .await
     }
 
     // 批量写入字软元件
     // 写入成功返回 Ok
-    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-    // 其它错误都返回 Err(0)
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
     pub async fn write_words(
-        &mut self,
+        &self,
         head_number: u32,
         dev: DeviceWord,
         data: &[u16],
-    ) -> Result<(), u16> {
-        if let Some(stream) = self.stream.first_mut() {
-            return write_words(stream, dev, head_number, data).await;
-        }
-        Err(0)
+    ) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            write_words(&conn, &head, dev, head_number, data).await
+        })
+        .await
     }
 
     // 批量写入位软元件
     // 写入成功返回 Ok
-    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-    // 其它错误都返回 Err(0)
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
     pub async fn write_bits(
-        &mut self,
+        &self,
         head_number: u32,
         dev: DeviceBit,
         data: &[bool],
-    ) -> Result<(), u16> {
-        if let Some(stream) = self.stream.first_mut() {
-            return write_bits(stream, dev, head_number, data).await;
-        }
-        Err(0)
+    ) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            write_bits(&conn, &head, dev, head_number, data).await
+        })
+        .await
     }
 
     // 批量读取多个块
     // 读取成功返回 值数组
-    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-    // 其它错误都返回 Err(0)
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
     pub async fn read_blocks(
-        &mut self,
-        data: &Vec<(u32, DeviceWord, u16)>,
-    ) -> Result<Vec<Vec<u16>>, u16> {
-        if let Some(stream) = self.stream.first_mut() {
-            return read_blocks(stream, data).await;
-        }
-        Err(0)
+        &self,
+        data: &[(u32, DeviceWord, u16)],
+    ) -> Result<Vec<Vec<u16>>, SlmpError> {
+        self.run(true, |conn, head| async move {
+            read_blocks(&conn, &head, data, &[]).await
+        })
+        .await
+        .map(|b| b.words)
+    }
+
+    // 批量读取多个块, 同时读取字软元件块和位软元件块
+    // 位软元件块的点数为字数, 每个字为16点, 低位为起始软元件
+    // 读取成功返回 字软元件块和位软元件块的值
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
+    pub async fn read_blocks_with_bits(
+        &self,
+        words: &[(u32, DeviceWord, u16)],
+        bits: &[(u32, DeviceBit, u16)],
+    ) -> Result<Blocks, SlmpError> {
+        self.run(true, |conn, head| async move {
+            read_blocks(&conn, &head, words, bits).await
+        })
+        .await
     }
 
     // 批量写多个块 (D软元件)
     // 写入成功返回 Ok
-    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-    // 其它错误都返回 Err(0)
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
     pub async fn write_blocks(
-        &mut self,
-        data: &Vec<(u32, DeviceWord, Vec<u16>)>,
-    ) -> Result<(), u16> {
-        if let Some(stream) = self.stream.first_mut() {
-            return write_blocks(stream, data).await;
+        &self,
+        data: &[(u32, DeviceWord, Vec<u16>)],
+    ) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            write_blocks(&conn, &head, data, &[]).await
+        })
+        .await
+    }
+
+    // 批量写多个块, 同时写入字软元件块和位软元件块
+    // 位软元件块每个字为16点, 低位为起始软元件
+    // 写入成功返回 Ok
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
+    pub async fn write_blocks_with_bits(
+        &self,
+        words: &[(u32, DeviceWord, Vec<u16>)],
+        bits: &[(u32, DeviceBit, Vec<u16>)],
+    ) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            write_blocks(&conn, &head, words, bits).await
+        })
+        .await
+    }
+
+    // 随机读取字软元件和双字软元件, 字访问的位软元件按16点读取
+    // 读取成功返回 以请求的地址为键的值
+    // words 或 dwords 中有重复的地址时返回 Err(SlmpError::InvalidRequest)
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
+    pub async fn read_random(
+        &self,
+        words: &[DeviceAddress],
+        dwords: &[DeviceAddress],
+    ) -> Result<RandomValues, SlmpError> {
+        self.run(true, |conn, head| async move {
+            read_random(&conn, &head, words, dwords).await
+        })
+        .await
+    }
+
+    // 随机写入字软元件和双字软元件, 字访问的位软元件按16点写入
+    // 写入成功返回 Ok
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
+    pub async fn write_random_words(
+        &self,
+        words: &[(DeviceAddress, u16)],
+        dwords: &[(DeviceAddress, u32)],
+    ) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            write_random_words(&conn, &head, words, dwords).await
+        })
+        .await
+    }
+
+    // 随机写入位软元件
+    // 写入成功返回 Ok
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
+    pub async fn write_random_bits(&self, data: &[(DeviceAddress, bool)]) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            write_random_bits(&conn, &head, data).await
+        })
+        .await
+    }
+
+    // 读取 CPU 型号名称和型号代码
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
+    pub async fn read_cpu_info(&self) -> Result<CpuInfo, SlmpError> {
+        self.run(true, |conn, head| async move {
+            read_type_name(&conn, &head).await
+        })
+        .await
+    }
+
+    // 折返测试, PLC 原样返回 data
+    // data 为 1-960 个 '0'-'9', 'A'-'F' 字符
+    // 返回的数据与 data 不一致时返回 Err(SlmpError::MalformedFrame)
+    pub async fn loopback(&self, data: &[u8]) -> Result<Vec<u8>, SlmpError> {
+        self.run(true, |conn, head| async move {
+            loopback(&conn, &head, data).await
+        })
+        .await
+    }
+
+    // 发送折返测试检查 PLC 是否响应
+    // 在当前连接上只发送一次, 不按重试策略重试, 也不等待重连, 直接返回第一次的错误
+    // 成功返回 请求的往返时间
+    // 未连接时返回 Err(SlmpError::NotConnected), 连接中断时返回 Err(SlmpError::Disconnected)
+    pub async fn ping(&self) -> Result<Duration, SlmpError> {
+        let conn = self.link.current().ok_or(SlmpError::NotConnected)?;
+        ping(&conn, &self.header(&conn)).await
+    }
+
+    // 远程密码解锁
+    // Q/L 系列密码为4个字符, iQ-R 系列为6-32个字符
+    // 密码不一致时返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
+    pub async fn unlock(&self, password: &str) -> Result<(), SlmpError> {
+        self.run(true, |conn, head| async move {
+            unlock(&conn, &head, password).await
+        })
+        .await
+    }
+
+    // 远程密码锁定
+    // 锁定后除解锁以外的请求返回 Err(SlmpError::PlcEndCode)
+    pub async fn lock(&self, password: &str) -> Result<(), SlmpError> {
+        self.run(true, |conn, head| async move {
+            lock(&conn, &head, password).await
+        })
+        .await
+    }
+
+    // 远程 RUN
+    // force 为 true 时, 其它设备远程 STOP/PAUSE 中也强制执行
+    // clear 为 RUN 时的软元件清除模式
+    // 执行成功返回 Ok
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode)
+    // 未连接时返回 Err(SlmpError::NotConnected)
+    pub async fn remote_run(&self, force: bool, clear: ClearMode) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            remote_run(&conn, &head, force, clear).await
+        })
+        .await
+    }
+
+    // 远程 STOP
+    // 执行成功返回 Ok
+    pub async fn remote_stop(&self) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            remote_stop(&conn, &head).await
+        })
+        .await
+    }
+
+    // 远程 PAUSE
+    // force 为 true 时, 其它设备远程 PAUSE 中也强制执行
+    // 执行成功返回 Ok
+    pub async fn remote_pause(&self, force: bool) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            remote_pause(&conn, &head, force).await
+        })
+        .await
+    }
+
+    // 远程锁存清除, CPU 需为 STOP 状态
+    // 执行成功返回 Ok
+    pub async fn remote_latch_clear(&self) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            remote_latch_clear(&conn, &head).await
+        })
+        .await
+    }
+
+    // 远程 RESET, CPU 需为 STOP 状态
+    // PLC 复位时可能不返回响应, 等待响应超时或连接中断时也返回 Ok
+    // 复位后连接会断开, 设置了重连策略时自动重连
+    pub async fn remote_reset(&self) -> Result<(), SlmpError> {
+        self.run(false, |conn, head| async move {
+            remote_reset(&conn, &head).await
+        })
+        .await
+    }
+
+    // 监视登录, 返回用于执行监视的 Monitor
+    // 登录的软元件与随机读相同, 之后 Monitor::poll 只发送执行监视指令
+    // 重连后 poll 自动重新登录
+    // 同一连接同一时间只有一个登录有效, 多个 Monitor 交替 poll 时每次都重新登录
+    pub async fn monitor(
+        &self,
+        words: &[DeviceAddress],
+        dwords: &[DeviceAddress],
+    ) -> Result<Monitor, SlmpError> {
+        Monitor::register(self, words, dwords).await
+    }
+
+    // 批量读取字软元件, 起始地址为 DeviceAddress
+    // 地址不是字软元件时返回 Err(SlmpError::InvalidRequest)
+    pub async fn read_words_at(
+        &self,
+        addr: DeviceAddress,
+        number: u16,
+    ) -> Result<Vec<u16>, SlmpError> {
+        let (head_number, dev) = addr.as_word()?;
+        self.read_words(head_number, dev, number).await
+    }
+
+    // 批量读取位软元件, 起始地址为 DeviceAddress
+    // 地址不是位软元件时返回 Err(SlmpError::InvalidRequest)
+    pub async fn read_bits_at(
+        &self,
+        addr: DeviceAddress,
+        number: u16,
+    ) -> Result<Vec<bool>, SlmpError> {
+        let (head_number, dev) = addr.as_bit()?;
+        self.read_bits(head_number, dev, number).await
+    }
+
+    // 批量写入字软元件, 起始地址为 DeviceAddress
+    // 地址不是字软元件时返回 Err(SlmpError::InvalidRequest)
+    pub async fn write_words_at(&self, addr: DeviceAddress, data: &[u16]) -> Result<(), SlmpError> {
+        let (head_number, dev) = addr.as_word()?;
+        self.write_words(head_number, dev, data).await
+    }
+
+    // 批量写入位软元件, 起始地址为 DeviceAddress
+    // 地址不是位软元件时返回 Err(SlmpError::InvalidRequest)
+    pub async fn write_bits_at(&self, addr: DeviceAddress, data: &[bool]) -> Result<(), SlmpError> {
+        let (head_number, dev) = addr.as_bit()?;
+        self.write_bits(head_number, dev, data).await
+    }
+
+    // 批量读取多个块, 块的起始地址为 DeviceAddress
+    // 地址不是字软元件时返回 Err(SlmpError::InvalidRequest)
+    pub async fn read_blocks_at(
+        &self,
+        data: &[(DeviceAddress, u16)],
+    ) -> Result<Vec<Vec<u16>>, SlmpError> {
+        let mut blocks = Vec::with_capacity(data.len());
+        for (addr, number) in data {
+            let (head_number, dev) = addr.as_word()?;
+            blocks.push((head_number, dev, *number));
         }
-        Err(0)
+        self.read_blocks(&blocks).await
+    }
+
+    // 批量写多个块, 块的起始地址为 DeviceAddress
+    // 地址不是字软元件时返回 Err(SlmpError::InvalidRequest)
+    pub async fn write_blocks_at(
+        &self,
+        data: &[(DeviceAddress, Vec<u16>)],
+    ) -> Result<(), SlmpError> {
+        let mut blocks = Vec::with_capacity(data.len());
+        for (addr, d) in data {
+            let (head_number, dev) = addr.as_word()?;
+            blocks.push((head_number, dev, d.clone()));
+        }
+        self.write_blocks(&blocks).await
+    }
+
+    // 批量读取多个字软元件块和位软元件块, 块的起始地址为 DeviceAddress
+    // words 中的地址不是字软元件, 或 bits 中的地址不是位软元件时返回 Err(SlmpError::InvalidRequest)
+    pub async fn read_blocks_with_bits_at(
+        &self,
+        words: &[(DeviceAddress, u16)],
+        bits: &[(DeviceAddress, u16)],
+    ) -> Result<Blocks, SlmpError> {
+        let mut word_blocks = Vec::with_capacity(words.len());
+        for (addr, number) in words {
+            let (head_number, dev) = addr.as_word()?;
+            word_blocks.push((head_number, dev, *number));
+        }
+        let mut bit_blocks = Vec::with_capacity(bits.len());
+        for (addr, number) in bits {
+            let (head_number, dev) = addr.as_bit()?;
+            bit_blocks.push((head_number, dev, *number));
+        }
+        self.read_blocks_with_bits(&word_blocks, &bit_blocks).await
+    }
+
+    // 批量写多个字软元件块和位软元件块, 块的起始地址为 DeviceAddress
+    // words 中的地址不是字软元件, 或 bits 中的地址不是位软元件时返回 Err(SlmpError::InvalidRequest)
+    pub async fn write_blocks_with_bits_at(
+        &self,
+        words: &[(DeviceAddress, Vec<u16>)],
+        bits: &[(DeviceAddress, Vec<u16>)],
+    ) -> Result<(), SlmpError> {
+        let mut word_blocks = Vec::with_capacity(words.len());
+        for (addr, d) in words {
+            let (head_number, dev) = addr.as_word()?;
+            word_blocks.push((head_number, dev, d.clone()));
+        }
+        let mut bit_blocks = Vec::with_capacity(bits.len());
+        for (addr, d) in bits {
+            let (head_number, dev) = addr.as_bit()?;
+            bit_blocks.push((head_number, dev, d.clone()));
+        }
+        self.write_blocks_with_bits(&word_blocks, &bit_blocks).await
     }
 }
 
 async fn _test_blocks() {
-    let mut slmp = Slmp::new();
+    let slmp = Slmp::new();
     let addr = SocketAddr::from(([172, 20, 1, 33], 5432));
     match slmp.connect(&addr).await {
         Ok(_) => {
             println!("connect successful");
 
             let r = slmp
-                .read_blocks(&vec![(1, DeviceWord::D, 10), (11, DeviceWord::D, 10)])
+                .read_blocks(&[(1, DeviceWord::D, 10), (11, DeviceWord::D, 10)])
                 .await;
             match r {
                 Ok(vlist) => {
@@ -159,18 +715,15 @@ async fn _test_blocks() {
                     }
                     println!("]");
                 }
-                Err(code) => {
-                    println!("read blocks err code = {}", code);
+                Err(e) => {
+                    println!("read blocks err = {}", e);
                 }
             }
 
             // async_std::task::sleep(Duration::from_secs(5)).await;
 
-            if let Err(code) = slmp
-                .write_blocks(&vec![(1, DeviceWord::D, vec![1, 1])])
-                .await
-            {
-                println!("write blocks err code = {}", code);
+            if let Err(e) = slmp.write_blocks(&[(1, DeviceWord::D, vec![1, 1])]).await {
+                println!("write blocks err = {}", e);
             } else {
                 println!("write blocks ok");
             }
@@ -178,7 +731,7 @@ async fn _test_blocks() {
             // async_std::task::sleep(Duration::from_secs(5)).await;
 
             let r = slmp
-                .read_blocks(&vec![(1, DeviceWord::D, 10), (11, DeviceWord::D, 10)])
+                .read_blocks(&[(1, DeviceWord::D, 10), (11, DeviceWord::D, 10)])
                 .await;
             match r {
                 Ok(vlist) => {
@@ -192,8 +745,8 @@ async fn _test_blocks() {
                     }
                     println!("]");
                 }
-                Err(code) => {
-                    println!("read blocks err code = {}", code);
+                Err(e) => {
+                    println!("read blocks err = {}", e);
                 }
             }
 
@@ -215,7 +768,7 @@ fn test_blocks() {
 }
 
 async fn _test_words() {
-    let mut slmp = Slmp::new();
+    let slmp = Slmp::new();
     let addr = SocketAddr::from(([172, 20, 1, 33], 5432));
     match slmp.connect(&addr).await {
         Ok(_) => {
@@ -230,12 +783,12 @@ async fn _test_words() {
                     }
                     println!("]");
                 }
-                Err(code) => {
-                    println!("read words err code = {}", code);
+                Err(e) => {
+                    println!("read words err = {}", e);
                 }
             }
 
-            if let Err(code) = slmp
+            if let Err(e) = slmp
                 .write_words(
                     1,
                     DeviceWord::D,
@@ -243,7 +796,7 @@ async fn _test_words() {
                 )
                 .await
             {
-                println!("write words err code = {}", code);
+                println!("write words err = {}", e);
             } else {
                 println!("write words ok");
             }
@@ -266,7 +819,7 @@ fn test_words() {
 }
 
 async fn _test_bits() {
-    let mut slmp = Slmp::new();
+    let slmp = Slmp::new();
     let addr = SocketAddr::from(([172, 20, 1, 33], 5432));
     match slmp.connect(&addr).await {
         Ok(_) => {
@@ -281,12 +834,12 @@ async fn _test_bits() {
                     }
                     println!("]");
                 }
-                Err(code) => {
-                    println!("read bits err code = {}", code);
+                Err(e) => {
+                    println!("read bits err = {}", e);
                 }
             }
 
-            if let Err(code) = slmp
+            if let Err(e) = slmp
                 .write_bits(
                     3,
                     DeviceBit::M,
@@ -294,7 +847,7 @@ async fn _test_bits() {
                 )
                 .await
             {
-                println!("write bits err code = {}", code);
+                println!("write bits err = {}", e);
             } else {
                 println!("write bits ok");
             }
@@ -315,3 +868,590 @@ fn test_bits() {
     let time = now_time.elapsed().as_millis();
     println!("test bits time = {}ms", time);
 }
+
+// 测试用: 3E 帧二进制响应, 结束代码为 0, data 为之后的响应数据
+#[cfg(test)]
+fn reply_3e(data: &[u8]) -> Vec<u8> {
+    let mut res = vec![0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00];
+    res.extend_from_slice(&(data.len() as u16 + 2).to_le_bytes());
+    res.extend_from_slice(&[0x00, 0x00]);
+    res.extend_from_slice(data);
+    res
+}
+
+// 测试用: 4E 帧二进制响应, 序列号与请求 req 相同
+#[cfg(test)]
+fn reply_4e(req: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut res = vec![0xD4, 0x00, req[2], req[3], 0x00, 0x00];
+    res.extend_from_slice(&reply_3e(data)[2..]);
+    res
+}
+
+// 测试用: 监听本地任意端口, 在后台任务中用 f 处理连接
+#[cfg(test)]
+async fn spawn_server<F, Fut, T>(f: F) -> (SocketAddr, async_std::task::JoinHandle<T>)
+where
+    F: FnOnce(async_std::net::TcpListener) -> Fut,
+    Fut: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let listener = async_std::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    (addr, async_std::task::spawn(f(listener)))
+}
+
+#[test]
+fn test_pipeline() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            //两个 4E 帧字读取请求
+            let mut req = [0u8; 50];
+            s.read_exact(&mut req).await.unwrap();
+            //倒序响应, 数据为请求的起始软元件编号
+            for r in req.chunks(25).rev() {
+                let res = reply_4e(r, &r[19..21]);
+                s.write_all(&res).await.unwrap();
+            }
+            s
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_frame_type(FrameType::Frame4E);
+        slmp.connect(&addr).await.unwrap();
+        let other = slmp.clone();
+        let t = async_std::task::spawn(async move { other.read_words(2, DeviceWord::D, 1).await });
+        let a = slmp.read_words(1, DeviceWord::D, 1).await.unwrap();
+        let b = t.await.unwrap();
+        assert_eq!(a, vec![1]);
+        assert_eq!(b, vec![2]);
+        let _s = server.await;
+        slmp.shutdown().unwrap();
+    });
+}
+
+#[test]
+fn test_udp_retransmit() {
+    use async_std::net::UdpSocket;
+    async_std::task::block_on(async {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let server = async_std::task::spawn(async move {
+            let mut req = [0u8; 64];
+            //丢弃第一次请求, 响应重发的请求
+            let (_, _) = server.recv_from(&mut req).await.unwrap();
+            let (n, peer) = server.recv_from(&mut req).await.unwrap();
+            assert_eq!(n, 25);
+            let res = reply_4e(&req, &[0x34, 0x12]);
+            server.send_to(&res, peer).await.unwrap();
+        });
+
+        let mut slmp = Slmp::new();
+        slmp.set_transport_type(TransportType::Udp);
+        assert!(matches!(
+            slmp.connect(&addr).await,
+            Err(SlmpError::InvalidRequest(_))
+        ));
+        slmp.set_frame_type(FrameType::Frame4E);
+        slmp.connect(&addr).await.unwrap();
+        let v = slmp.read_words(0, DeviceWord::D, 1).await.unwrap();
+        assert_eq!(v, vec![0x1234]);
+        server.await;
+        slmp.shutdown().unwrap();
+    });
+}
+
+#[test]
+fn test_blocks_with_bits_at() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut req = [0u8; 29];
+            s.read_exact(&mut req).await.unwrap();
+            assert_eq!(
+                req[11..],
+                [
+                    0x06, 0x04, 0x00, 0x00, 0x01, 0x01, 0x64, 0x00, 0x00, 0xA8, 0x01, 0x00, 0x10,
+                    0x00, 0x00, 0x9C, 0x01, 0x00
+                ]
+            );
+            s.write_all(&reply_3e(&[0x34, 0x12, 0x05, 0x80]))
+                .await
+                .unwrap();
+            s
+        })
+        .await;
+
+        let slmp = Slmp::new();
+        slmp.connect(&addr).await.unwrap();
+        let d100: DeviceAddress = "D100".parse().unwrap();
+        let x10: DeviceAddress = "X10".parse().unwrap();
+        let r = slmp.read_blocks_with_bits_at(&[(x10, 1)], &[]).await;
+        assert!(matches!(r, Err(SlmpError::InvalidRequest(_))));
+        let v = slmp
+            .read_blocks_with_bits_at(&[(d100, 1)], &[(x10, 1)])
+            .await
+            .unwrap();
+        assert_eq!(v.words, vec![vec![0x1234]]);
+        assert_eq!(v.bits, vec![vec![0x8005]]);
+        let _s = server.await;
+        slmp.shutdown().unwrap();
+    });
+}
+
+#[test]
+fn test_response_timeout() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut req = [0u8; 21];
+            s.read_exact(&mut req).await.unwrap();
+            //每 100ms 发送一个字节, 响应一直不完整
+            let res = reply_3e(&[0x00, 0x00]);
+            for b in res[..11].iter() {
+                if s.write_all(&[*b]).await.is_err() {
+                    break;
+                }
+                async_std::task::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_config(SlmpConfig {
+            response_timeout: Duration::from_millis(300),
+            ..SlmpConfig::default()
+        });
+        slmp.set_retry_policy(RetryPolicy::none());
+        slmp.connect(&addr).await.unwrap();
+        let now = Instant::now();
+        let r = slmp.read_words(0, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::Timeout)));
+        assert!(now.elapsed() < Duration::from_millis(800));
+        slmp.shutdown().unwrap();
+        server.await;
+    });
+}
+
+#[test]
+fn test_late_reply_3e() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            //请求超时后才响应, 迟到的响应不能被当作下一个请求的响应
+            let mut req = [0u8; 21];
+            s.read_exact(&mut req).await.unwrap();
+            async_std::task::sleep(Duration::from_millis(300)).await;
+            let res = reply_3e(&[0x64, 0x00]);
+            let _ = s.write_all(&res).await;
+            let _ = s.read(&mut req).await;
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_config(SlmpConfig {
+            response_timeout: Duration::from_millis(200),
+            ..SlmpConfig::default()
+        });
+        slmp.set_retry_policy(RetryPolicy::none());
+        slmp.connect(&addr).await.unwrap();
+        let r = slmp.read_words(100, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::Timeout)));
+        assert_eq!(slmp.state(), ConnectionState::Disconnected);
+        async_std::task::sleep(Duration::from_millis(200)).await;
+        let r = slmp.read_words(200, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::Disconnected)));
+        server.await;
+        slmp.shutdown().unwrap();
+    });
+}
+
+#[test]
+fn test_cancel_3e() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            //请求被取消后才响应, 之后不应再收到请求
+            let mut req = [0u8; 21];
+            s.read_exact(&mut req).await.unwrap();
+            async_std::task::sleep(Duration::from_millis(200)).await;
+            let _ = s.write_all(&reply_3e(&[0x64, 0x00])).await;
+            let n = s.read(&mut req).await.unwrap_or(0);
+            assert_eq!(n, 0);
+        })
+        .await;
+
+        let slmp = Slmp::new();
+        slmp.connect(&addr).await.unwrap();
+        let r = slmp.read_words(100, DeviceWord::D, 1);
+        assert!(async_std::future::timeout(Duration::from_millis(100), r)
+            .await
+            .is_err());
+        assert_eq!(slmp.state(), ConnectionState::Disconnected);
+        async_std::task::sleep(Duration::from_millis(200)).await;
+        let r = slmp.read_words(200, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::Disconnected)));
+        server.await;
+        slmp.shutdown().unwrap();
+    });
+}
+
+#[test]
+fn test_ping() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut req = [0u8; 33];
+            s.read_exact(&mut req).await.unwrap();
+            let mut data = vec![0x10, 0x00];
+            data.extend_from_slice(&req[17..]);
+            s.write_all(&reply_3e(&data)).await.unwrap();
+            //第二次不响应, ping 不重试
+            s.read_exact(&mut req).await.unwrap();
+            let n = s.read(&mut req).await.unwrap_or(0);
+            assert_eq!(n, 0);
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_config(SlmpConfig {
+            response_timeout: Duration::from_millis(200),
+            ..SlmpConfig::default()
+        });
+        assert!(matches!(slmp.ping().await, Err(SlmpError::NotConnected)));
+        slmp.connect(&addr).await.unwrap();
+        assert!(slmp.ping().await.is_ok());
+        let now = Instant::now();
+        assert!(matches!(slmp.ping().await, Err(SlmpError::Timeout)));
+        assert!(now.elapsed() < Duration::from_millis(400));
+        slmp.shutdown().unwrap();
+        server.await;
+    });
+}
+
+#[test]
+fn test_disconnect() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            //收到请求后关闭连接
+            let mut req = [0u8; 21];
+            s.read_exact(&mut req).await.unwrap();
+        })
+        .await;
+
+        let slmp = Slmp::new();
+        assert_eq!(slmp.state(), ConnectionState::NotConnected);
+        slmp.connect(&addr).await.unwrap();
+        assert!(slmp.is_connected());
+        let r = slmp.read_words(0, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::Disconnected)));
+        assert_eq!(slmp.state(), ConnectionState::Disconnected);
+        //没有重连策略时不重试, 立即返回
+        let now = Instant::now();
+        let r = slmp.read_words(0, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::Disconnected)));
+        assert!(now.elapsed() < Duration::from_millis(50));
+        server.await;
+        slmp.shutdown().unwrap();
+        assert_eq!(slmp.state(), ConnectionState::NotConnected);
+    });
+}
+
+#[test]
+fn test_malformed_stream() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut req = [0u8; 21];
+            s.read_exact(&mut req).await.unwrap();
+            s.write_all(&[0xFF; 13]).await.unwrap();
+            s
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_retry_policy(RetryPolicy::none());
+        slmp.connect(&addr).await.unwrap();
+        let r = slmp.read_words(0, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::MalformedFrame)));
+        assert_eq!(slmp.state(), ConnectionState::Disconnected);
+        let _s = server.await;
+        slmp.shutdown().unwrap();
+    });
+}
+
+#[test]
+fn test_reconnect() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            //第一个连接收到请求后关闭
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut req = [0u8; 21];
+            s.read_exact(&mut req).await.unwrap();
+            drop(s);
+            //重连后正常响应
+            let (mut s, _) = listener.accept().await.unwrap();
+            s.read_exact(&mut req).await.unwrap();
+            let res = reply_3e(&[0x34, 0x12]);
+            s.write_all(&res).await.unwrap();
+            s
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_config(SlmpConfig {
+            reconnect: Some(ReconnectPolicy {
+                initial_delay: Duration::from_millis(10),
+                ..ReconnectPolicy::default()
+            }),
+            ..SlmpConfig::default()
+        });
+        let states = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let s = states.clone();
+        slmp.on_state_change(move |state| s.lock().unwrap().push(state));
+        slmp.connect(&addr).await.unwrap();
+        let v = slmp.read_words(0, DeviceWord::D, 1).await.unwrap();
+        assert_eq!(v, vec![0x1234]);
+        assert!(slmp.is_connected());
+        assert_eq!(
+            *states.lock().unwrap(),
+            vec![
+                ConnectionState::Connected,
+                ConnectionState::Disconnected,
+                ConnectionState::Reconnecting,
+                ConnectionState::Connected
+            ]
+        );
+        let _s = server.await;
+        slmp.shutdown().unwrap();
+    });
+}
+
+#[test]
+fn test_retry_3e() {
+    use async_std::{io::timeout, prelude::*};
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let late = reply_3e(&[0x64, 0x00]);
+            //没有重连策略时超时不重试
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut req = [0u8; 21];
+            s.read_exact(&mut req).await.unwrap();
+            let r = timeout(Duration::from_millis(400), s.read_exact(&mut req)).await;
+            assert!(r.is_err());
+            //有重连策略时, 迟到的响应不会被当作重试的响应
+            let (mut s, _) = listener.accept().await.unwrap();
+            s.read_exact(&mut req).await.unwrap();
+            async_std::task::sleep(Duration::from_millis(250)).await;
+            let _ = s.write_all(&late).await;
+            let (mut s, _) = listener.accept().await.unwrap();
+            s.read_exact(&mut req).await.unwrap();
+            let res = reply_3e(&[0x34, 0x12]);
+            s.write_all(&res).await.unwrap();
+            s
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        let config = SlmpConfig {
+            response_timeout: Duration::from_millis(200),
+            ..SlmpConfig::default()
+        };
+        slmp.set_config(config);
+        slmp.connect(&addr).await.unwrap();
+        let r = slmp.read_words(0, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::Timeout)));
+        slmp.shutdown().unwrap();
+
+        slmp.set_config(SlmpConfig {
+            reconnect: Some(ReconnectPolicy {
+                initial_delay: Duration::from_millis(10),
+                ..ReconnectPolicy::default()
+            }),
+            ..config
+        });
+        slmp.connect(&addr).await.unwrap();
+        let v = slmp.read_words(0, DeviceWord::D, 1).await.unwrap();
+        assert_eq!(v, vec![0x1234]);
+        let _s = server.await;
+        slmp.shutdown().unwrap();
+    });
+}
+
+#[test]
+fn test_reconnect_give_up() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        //接受一个连接, 收到请求后关闭, 之后拒绝连接
+        let serve_once = || async {
+            spawn_server(|listener| async move {
+                let (mut s, _) = listener.accept().await.unwrap();
+                let mut req = [0u8; 21];
+                s.read_exact(&mut req).await.unwrap();
+            })
+            .await
+        };
+        let slmp_with = |initial_delay| {
+            let mut slmp = Slmp::new();
+            slmp.set_config(SlmpConfig {
+                reconnect: Some(ReconnectPolicy {
+                    initial_delay,
+                    max_attempts: Some(2),
+                    jitter: false,
+                    ..ReconnectPolicy::default()
+                }),
+                ..SlmpConfig::default()
+            });
+            slmp.set_retry_policy(RetryPolicy::none());
+            slmp
+        };
+
+        //重连次数用完后不再重连
+        let (addr, server) = serve_once().await;
+        let slmp = slmp_with(Duration::from_millis(10));
+        let states = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let s = states.clone();
+        slmp.on_state_change(move |state| s.lock().unwrap().push(state));
+        slmp.connect(&addr).await.unwrap();
+        let r = slmp.read_words(0, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::Disconnected)));
+        server.await;
+        let r = slmp.read_words(0, DeviceWord::D, 1).await;
+        assert!(matches!(r, Err(SlmpError::Disconnected)));
+        assert_eq!(slmp.state(), ConnectionState::Disconnected);
+        let reconnecting = states
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|s| **s == ConnectionState::Reconnecting)
+            .count();
+        assert_eq!(reconnecting, 1);
+        slmp.shutdown().unwrap();
+
+        //重连等待中 shutdown 后立即为 NotConnected
+        let (addr, server) = serve_once().await;
+        let slmp = slmp_with(Duration::from_secs(10));
+        slmp.connect(&addr).await.unwrap();
+        let r = slmp.read_words(0, DeviceWord::D, 1);
+        let _ = async_std::future::timeout(Duration::from_millis(200), r).await;
+        server.await;
+        assert_eq!(slmp.state(), ConnectionState::Reconnecting);
+        slmp.shutdown().unwrap();
+        assert_eq!(slmp.state(), ConnectionState::NotConnected);
+    });
+}
+
+#[test]
+fn test_retry() {
+    use async_std::{io::timeout, prelude::*};
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            //不响应第一次读取请求, 响应重试的请求
+            let mut req = [0u8; 25];
+            s.read_exact(&mut req).await.unwrap();
+            s.read_exact(&mut req).await.unwrap();
+            let res = reply_4e(&req, &[0x34, 0x12]);
+            s.write_all(&res).await.unwrap();
+            //写入请求超时后不重试
+            let mut req = [0u8; 27];
+            s.read_exact(&mut req).await.unwrap();
+            let r = timeout(Duration::from_millis(400), s.read(&mut req)).await;
+            assert!(r.is_err());
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_frame_type(FrameType::Frame4E);
+        slmp.set_config(SlmpConfig {
+            response_timeout: Duration::from_millis(200),
+            ..SlmpConfig::default()
+        });
+        slmp.connect(&addr).await.unwrap();
+        let v = slmp.read_words(0, DeviceWord::D, 1).await.unwrap();
+        assert_eq!(v, vec![0x1234]);
+        let r = slmp.write_words(0, DeviceWord::D, &[1]).await;
+        assert!(matches!(r, Err(SlmpError::Timeout)));
+        server.await;
+        slmp.shutdown().unwrap();
+    });
+}
+
+#[test]
+fn test_remote_reset() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let mut req = [0u8; 17];
+            //收到请求后不响应就关闭
+            let (mut s, _) = listener.accept().await.unwrap();
+            s.read_exact(&mut req).await.unwrap();
+            assert_eq!(req[11..], [0x06, 0x10, 0x00, 0x00, 0x01, 0x00]);
+            drop(s);
+            //收到请求后一直不响应
+            let (mut s, _) = listener.accept().await.unwrap();
+            s.read_exact(&mut req).await.unwrap();
+            let _ = s.read(&mut req).await;
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_config(SlmpConfig {
+            response_timeout: Duration::from_millis(200),
+            ..SlmpConfig::default()
+        });
+        slmp.connect(&addr).await.unwrap();
+        slmp.remote_reset().await.unwrap();
+        //请求没有发出时返回错误
+        let r = slmp.remote_reset().await;
+        assert!(matches!(r, Err(SlmpError::Disconnected)));
+        slmp.shutdown().unwrap();
+        slmp.connect(&addr).await.unwrap();
+        slmp.remote_reset().await.unwrap();
+        slmp.shutdown().unwrap();
+        server.await;
+    });
+}
+
+#[test]
+fn test_auto_unlock() {
+    use async_std::prelude::*;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            //连接后先收到解锁请求
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut req = [0u8; 19];
+            s.read_exact(&mut req).await.unwrap();
+            assert_eq!(req[11..], [0x30, 0x16, 0x00, 0x00, b'P', b'A', b'S', b'S']);
+            let res = reply_3e(&[]);
+            s.write_all(&res).await.unwrap();
+            let mut req = [0u8; 21];
+            s.read_exact(&mut req).await.unwrap();
+            let res = reply_3e(&[0x34, 0x12]);
+            s.write_all(&res).await.unwrap();
+            s
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_password(Some("PASS"));
+        slmp.connect(&addr).await.unwrap();
+        let v = slmp.read_words(0, DeviceWord::D, 1).await.unwrap();
+        assert_eq!(v, vec![0x1234]);
+        let _s = server.await;
+        slmp.shutdown().unwrap();
+    });
+}
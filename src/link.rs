@@ -0,0 +1,290 @@
+// 连接管理
+// 克隆的 Slmp 共用同一个 Link
+// 保存 connect 时的参数, 连接中断后按重连策略在后台重新连接
+use crate::config::SlmpConfig;
+use crate::connection::{Connection, ConnectionState};
+use crate::error::SlmpError;
+use crate::slmp_core::{unlock, CpuSeries, DataCode, FrameType};
+use crate::transport::{Tcp, Transport, TransportType, Udp};
+use async_std::channel::{bounded, Sender};
+use async_std::{future::timeout, io::timeout as io_timeout};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+type Listener = Arc<dyn Fn(ConnectionState) + Send + Sync>;
+
+//建立连接的参数
+#[derive(Clone)]
+pub(crate) struct Params {
+    pub(crate) addr: SocketAddr,
+    pub(crate) frame: FrameType,
+    pub(crate) code: DataCode,
+    pub(crate) series: CpuSeries,
+    pub(crate) transport: TransportType,
+    pub(crate) config: SlmpConfig,
+    pub(crate) password: Option<String>, //连接后自动解锁的远程密码
+}
+
+impl Params {
+    async fn open(&self) -> Result<Connection, SlmpError> {
+        let transport: Arc<dyn Transport> = match self.transport {
+            TransportType::Tcp => {
+                Arc::new(io_timeout(self.config.connect_timeout, Tcp::connect(&self.addr)).await?)
+            }
+            TransportType::Udp => Arc::new(Udp::connect(&self.addr).await?),
+        };
+        let conn = Connection::new(transport, self.frame, self.code, self.series, self.config);
+        //解锁成功后才使用此连接
+        if let Some(password) = &self.password {
+            if let Err(e) = unlock(&conn, &conn.header(), password).await {
+                conn.close();
+                return Err(e);
+            }
+        }
+        Ok(conn)
+    }
+}
+
+pub(crate) struct Link {
+    conn: Mutex<Option<Arc<Connection>>>, //当前连接
+    params: Mutex<Option<Params>>,        //connect 时保存, shutdown 时清除
+    generation: AtomicU64,                //每次建立新连接或 shutdown 时加一
+    reconnecting: AtomicBool,             //后台重连任务运行中
+    gave_up: AtomicBool,                  //重连次数用完, 调用 connect 前不再重连
+    waiters: Mutex<Vec<Sender<()>>>,      //等待重连结束的请求
+    listeners: Mutex<Vec<Listener>>,      //连接状态变化回调
+}
+
+impl Link {
+    pub(crate) fn new() -> Link {
+        Link {
+            conn: Mutex::new(None),
+            params: Mutex::new(None),
+            generation: AtomicU64::new(0),
+            reconnecting: AtomicBool::new(false),
+            gave_up: AtomicBool::new(false),
+            waiters: Mutex::new(Vec::new()),
+            listeners: Mutex::new(Vec::new()),
+        }
+    }
+
+    // 当前连接, 可能已中断
+    pub(crate) fn current(&self) -> Option<Arc<Connection>> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn params(&self) -> Option<Params> {
+        self.params
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub(crate) fn policy_enabled(&self) -> bool {
+        let params = self.params.lock().unwrap_or_else(|e| e.into_inner());
+        matches!(&*params, Some(p) if p.config.reconnect.is_some())
+    }
+
+    pub(crate) fn state(&self) -> ConnectionState {
+        //shutdown 后重连任务可能还在等待, 先判断连接
+        match self.current() {
+            None => ConnectionState::NotConnected,
+            Some(c) if !c.is_closed() => ConnectionState::Connected,
+            _ if self.reconnecting.load(Ordering::Acquire) => ConnectionState::Reconnecting,
+            Some(c) if c.is_lost() => ConnectionState::Disconnected,
+            Some(_) => ConnectionState::NotConnected,
+        }
+    }
+
+    pub(crate) fn add_listener(&self, f: Listener) {
+        let mut listeners = self.listeners.lock().unwrap_or_else(|e| e.into_inner());
+        listeners.push(f);
+    }
+
+    fn notify(&self, state: ConnectionState) {
+        let listeners = self
+            .listeners
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        for f in listeners {
+            f(state);
+        }
+    }
+
+    // 建立连接
+    // 已有连接时返回 Err(SlmpError::InvalidRequest)
+    pub(crate) async fn connect(self: &Arc<Self>, params: Params) -> Result<(), SlmpError> {
+        if let Some(c) = self.current() {
+            if !c.is_closed() {
+                return Err(SlmpError::InvalidRequest("already connected".to_string()));
+            }
+        }
+        let conn = params.open().await?;
+        *self.params.lock().unwrap_or_else(|e| e.into_inner()) = Some(params);
+        self.install(conn, self.generation.load(Ordering::Acquire));
+        Ok(())
+    }
+
+    // 保存新连接
+    // generation 已变化(期间调用了 connect 或 shutdown)时丢弃
+    fn install(self: &Arc<Self>, conn: Connection, generation: u64) {
+        let conn = Arc::new(conn);
+        {
+            let mut c = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+            if self
+                .generation
+                .compare_exchange(
+                    generation,
+                    generation + 1,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_err()
+            {
+                conn.close();
+                return;
+            }
+            if let Some(old) = c.replace(conn.clone()) {
+                old.close();
+            }
+            self.gave_up.store(false, Ordering::Release);
+        }
+        self.notify(ConnectionState::Connected);
+        //不持有 Link, 避免循环引用
+        let link = Arc::downgrade(self);
+        conn.set_on_lost(move || {
+            if let Some(link) = link.upgrade() {
+                link.notify(ConnectionState::Disconnected);
+                if link.policy_enabled() {
+                    link.spawn_reconnect();
+                }
+            }
+        });
+    }
+
+    // 断开连接, 停止重连
+    pub(crate) fn shutdown(&self) {
+        let conn = {
+            let mut c = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            *self.params.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            c.take()
+        };
+        if let Some(c) = conn {
+            c.close();
+            self.notify(ConnectionState::NotConnected);
+        }
+    }
+
+    // 获取可用的连接
+    // 连接中断且设置了重连策略时, 等待重连完成, 最多等待 wait
+    pub(crate) async fn connection(
+        self: &Arc<Self>,
+        wait: Duration,
+    ) -> Result<Arc<Connection>, SlmpError> {
+        match self.current() {
+            None => Err(SlmpError::NotConnected),
+            Some(c) if c.is_lost() && self.policy_enabled() => self.wait_reconnect(wait).await,
+            Some(c) => Ok(c),
+        }
+    }
+
+    // 等待后台重连, 超时或重连失败返回 Err(SlmpError::Disconnected)
+    // 重连次数用完后直接返回 Err(SlmpError::Disconnected), 不再重连
+    pub(crate) async fn wait_reconnect(
+        self: &Arc<Self>,
+        wait: Duration,
+    ) -> Result<Arc<Connection>, SlmpError> {
+        let rx = {
+            let mut waiters = self.waiters.lock().unwrap_or_else(|e| e.into_inner());
+            //其它任务已经重连
+            match self.current() {
+                None => return Err(SlmpError::NotConnected),
+                Some(c) if !c.is_closed() => return Ok(c),
+                _ => {}
+            }
+            //在 waiters 锁内判断, 重连任务先设置 gave_up 再通知 waiters
+            if self.gave_up.load(Ordering::Acquire) {
+                return Err(SlmpError::Disconnected);
+            }
+            let (tx, rx) = bounded(1);
+            waiters.push(tx);
+            rx
+        };
+        self.spawn_reconnect();
+        let _ = timeout(wait, rx.recv()).await;
+        match self.current() {
+            None => Err(SlmpError::NotConnected),
+            Some(c) if !c.is_closed() => Ok(c),
+            Some(_) => Err(SlmpError::Disconnected),
+        }
+    }
+
+    fn spawn_reconnect(self: &Arc<Self>) {
+        if self.gave_up.load(Ordering::Acquire) || self.reconnecting.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let link = Arc::downgrade(self);
+        async_std::task::spawn(async move {
+            let gave_up = reconnect(&link).await;
+            if let Some(link) = link.upgrade() {
+                if let Some(generation) = gave_up {
+                    //与 install/shutdown 使用同一个锁, 期间调用了 connect/shutdown 时不算放弃
+                    let _c = link.conn.lock().unwrap_or_else(|e| e.into_inner());
+                    if link.generation.load(Ordering::Acquire) == generation {
+                        link.gave_up.store(true, Ordering::Release);
+                    }
+                }
+                link.reconnecting.store(false, Ordering::Release);
+                let waiters: Vec<Sender<()>> = {
+                    let mut w = link.waiters.lock().unwrap_or_else(|e| e.into_inner());
+                    w.drain(..).collect()
+                };
+                for tx in waiters {
+                    let _ = tx.try_send(());
+                }
+                if link.state() == ConnectionState::Disconnected {
+                    link.notify(ConnectionState::Disconnected);
+                }
+            }
+        });
+    }
+}
+
+// 后台重连
+// 所有 Slmp 都已释放, 或期间调用了 connect/shutdown 时停止
+// 重连次数用完时返回开始重连时的 generation
+async fn reconnect(link: &Weak<Link>) -> Option<u64> {
+    let (params, generation) = match link.upgrade() {
+        Some(l) => match l.params() {
+            Some(p) => (p, l.generation.load(Ordering::Acquire)),
+            None => return None,
+        },
+        None => return None,
+    };
+    let policy = match params.config.reconnect {
+        Some(p) => p,
+        None => return None,
+    };
+    if let Some(l) = link.upgrade() {
+        l.notify(ConnectionState::Reconnecting);
+    }
+
+    let mut attempt = 0;
+    while policy.max_attempts.is_none_or(|max| attempt < max) {
+        async_std::task::sleep(policy.delay(attempt)).await;
+        attempt += 1;
+        let l = match link.upgrade() {
+            Some(l) if l.generation.load(Ordering::Acquire) == generation => l,
+            _ => return None,
+        };
+        if let Ok(conn) = params.open().await {
+            l.install(conn, generation);
+            return None;
+        }
+    }
+    Some(generation)
+}
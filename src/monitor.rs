@@ -0,0 +1,123 @@
+// 监视
+// 登录一次软元件, 之后每次 poll 只发送执行监视指令, 不重复发送软元件列表
+// 监视登录属于连接, 重连后或同一连接登录了其它 Monitor 时, poll 先重新登录
+use crate::address::DeviceAddress;
+use crate::error::{ErrorKind, SlmpError};
+use crate::slmp_core::{entry_monitor, execute_monitor, MonitorValues};
+use crate::Slmp;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//Monitor 的编号, 0 表示连接上没有登录
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+pub struct Monitor {
+    slmp: Slmp,
+    id: u64,
+    words: Vec<DeviceAddress>,  //字访问软元件
+    dwords: Vec<DeviceAddress>, //双字访问软元件
+}
+
+impl Monitor {
+    pub(crate) async fn register(
+        slmp: &Slmp,
+        words: &[DeviceAddress],
+        dwords: &[DeviceAddress],
+    ) -> Result<Monitor, SlmpError> {
+        let monitor = Monitor {
+            slmp: slmp.clone(),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            words: Vec::from(words),
+            dwords: Vec::from(dwords),
+        };
+        monitor
+            .slmp
+            .run(true, |conn, head| {
+                let monitor = &monitor;
+                async move {
+                    let mut registered = conn.monitor().lock().await;
+                    *registered = 0;
+                    entry_monitor(&conn, &head, &monitor.words, &monitor.dwords).await?;
+                    *registered = monitor.id;
+                    Ok(())
+                }
+            })
+            .await?;
+        Ok(monitor)
+    }
+
+    pub fn words(&self) -> &[DeviceAddress] {
+        &self.words
+    }
+
+    pub fn dwords(&self) -> &[DeviceAddress] {
+        &self.dwords
+    }
+
+    // 执行监视
+    // 读取成功返回 按登录顺序排列的值
+    // 通信正常,slmp协议返回的结束代码非零时,返回 Err(SlmpError::PlcEndCode), 下次 poll 重新登录
+    // 未连接时返回 Err(SlmpError::NotConnected)
+    pub async fn poll(&self) -> Result<MonitorValues, SlmpError> {
+        self.slmp
+            .run(true, |conn, mut head| async move {
+                let mut registered = conn.monitor().lock().await;
+                if *registered != self.id {
+                    *registered = 0;
+                    entry_monitor(&conn, &head, &self.words, &self.dwords).await?;
+                    *registered = self.id;
+                    head = self.slmp.header(&conn);
+                }
+                let r = execute_monitor(&conn, &head, self.words.len(), self.dwords.len()).await;
+                //PLC 复位等原因使登录失效
+                if matches!(&r, Err(e) if e.kind() == ErrorKind::PlcEndCode) {
+                    *registered = 0;
+                }
+                r
+            })
+            .await
+    }
+}
+
+#[test]
+fn test_monitor_reconnect() {
+    use crate::{reply_3e, spawn_server, DeviceWord, ReconnectPolicy, SlmpConfig};
+    use async_std::prelude::*;
+    use std::time::Duration;
+    async_std::task::block_on(async {
+        let (addr, server) = spawn_server(|listener| async move {
+            let entry = reply_3e(&[]);
+            let values = [reply_3e(&[0x34, 0x12]), reply_3e(&[0x78, 0x56])];
+            //每个连接都先登录再执行监视, 第一个连接执行一次后关闭
+            for v in values.iter() {
+                let (mut s, _) = listener.accept().await.unwrap();
+                let mut req = [0u8; 21];
+                s.read_exact(&mut req).await.unwrap();
+                assert_eq!(req[11..13], [0x01, 0x08]);
+                s.write_all(&entry).await.unwrap();
+                let mut req = [0u8; 15];
+                s.read_exact(&mut req).await.unwrap();
+                assert_eq!(req[11..13], [0x02, 0x08]);
+                s.write_all(v).await.unwrap();
+            }
+        })
+        .await;
+
+        let mut slmp = Slmp::new();
+        slmp.set_config(SlmpConfig {
+            reconnect: Some(ReconnectPolicy {
+                initial_delay: Duration::from_millis(10),
+                ..ReconnectPolicy::default()
+            }),
+            ..SlmpConfig::default()
+        });
+        slmp.connect(&addr).await.unwrap();
+        let monitor = slmp
+            .monitor(&[DeviceAddress::word(DeviceWord::D, 0)], &[])
+            .await
+            .unwrap();
+        assert_eq!(monitor.poll().await.unwrap().words, vec![0x1234]);
+        assert_eq!(monitor.poll().await.unwrap().words, vec![0x5678]);
+        server.await;
+        slmp.shutdown().unwrap();
+    });
+}
@@ -1,26 +1,238 @@
-use async_std::{io::timeout, net::TcpStream, prelude::*};
-use std::time::Duration;
+use crate::address::DeviceAddress;
+use crate::connection::Connection;
+use crate::error::{ErrorInfo, PlcErrorCode, SlmpError};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
 //字软元件
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DeviceWord {
-    D = 0xA8,  //数据寄存器 D
-    R = 0xAF,  //文件寄存器 R
-    ZR = 0xB0, //文件寄存器 ZR
+    D = 0xA8,    //数据寄存器 D
+    R = 0xAF,    //文件寄存器 R
+    ZR = 0xB0,   //文件寄存器 ZR
+    W = 0xB4,    //链接寄存器 W
+    SD = 0xA9,   //特殊寄存器 SD
+    SW = 0xB5,   //链接特殊寄存器 SW
+    TN = 0xC2,   //定时器当前值 TN
+    CN = 0xC5,   //计数器当前值 CN
+    STN = 0xC8,  //累计定时器当前值 STN
+    Z = 0xCC,    //变址寄存器 Z
+    LTN = 0x52,  //超长定时器当前值 LTN, 仅 iQ-R
+    LSTN = 0x5A, //超长累计定时器当前值 LSTN, 仅 iQ-R
+    LCN = 0x56,  //超长计数器当前值 LCN, 仅 iQ-R
+    LZ = 0x62,   //超长变址寄存器 LZ, 仅 iQ-R
+    RD = 0x2C,   //刷新数据寄存器 RD, 仅 iQ-R
 }
 
 //位软元件
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DeviceBit {
-    X = 0x9C, //输入继电器 X
-    Y = 0x9D, //输出继电器 Y
-    M = 0x90, //内部继电器 M
+    X = 0x9C,   //输入继电器 X
+    Y = 0x9D,   //输出继电器 Y
+    M = 0x90,   //内部继电器 M
+    B = 0xA0,   //链接继电器 B
+    L = 0x92,   //锁存继电器 L
+    F = 0x93,   //报警器 F
+    V = 0x94,   //变址继电器 V
+    SM = 0x91,  //特殊继电器 SM
+    SB = 0xA1,  //链接特殊继电器 SB
+    DX = 0xA2,  //直接访问输入 DX
+    DY = 0xA3,  //直接访问输出 DY
+    TS = 0xC1,  //定时器触点 TS, 只读
+    TC = 0xC0,  //定时器线圈 TC
+    CS = 0xC4,  //计数器触点 CS, 只读
+    CC = 0xC3,  //计数器线圈 CC
+    STS = 0xC7, //累计定时器触点 STS, 只读
+    STC = 0xC6, //累计定时器线圈 STC
+}
+
+pub(crate) const WORD_DEVICES: [DeviceWord; 15] = [
+    DeviceWord::D,
+    DeviceWord::R,
+    DeviceWord::ZR,
+    DeviceWord::W,
+    DeviceWord::SD,
+    DeviceWord::SW,
+    DeviceWord::TN,
+    DeviceWord::CN,
+    DeviceWord::STN,
+    DeviceWord::Z,
+    DeviceWord::LTN,
+    DeviceWord::LSTN,
+    DeviceWord::LCN,
+    DeviceWord::LZ,
+    DeviceWord::RD,
+];
+
+pub(crate) const BIT_DEVICES: [DeviceBit; 17] = [
+    DeviceBit::X,
+    DeviceBit::Y,
+    DeviceBit::M,
+    DeviceBit::B,
+    DeviceBit::L,
+    DeviceBit::F,
+    DeviceBit::V,
+    DeviceBit::SM,
+    DeviceBit::SB,
+    DeviceBit::DX,
+    DeviceBit::DY,
+    DeviceBit::TS,
+    DeviceBit::TC,
+    DeviceBit::CS,
+    DeviceBit::CC,
+    DeviceBit::STS,
+    DeviceBit::STC,
+];
+
+//软元件编码
+pub(crate) trait Device: Copy {
+    //二进制软元件代码
+    fn code(&self) -> u8;
+    //软元件名称
+    fn name(&self) -> &'static str;
+    //ASCII软元件代码
+    fn ascii(&self) -> &'static str;
+    //软元件编号是否为16进制
+    fn is_hex(&self) -> bool;
+    //只有 iQ-R 系列支持, 需要2字节软元件代码
+    fn iqr_only(&self) -> bool {
+        false
+    }
+    //只读软元件, 写入请求不发送
+    fn is_read_only(&self) -> bool {
+        false
+    }
+}
+
+impl Device for DeviceWord {
+    fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            DeviceWord::D => "D",
+            DeviceWord::R => "R",
+            DeviceWord::ZR => "ZR",
+            DeviceWord::W => "W",
+            DeviceWord::SD => "SD",
+            DeviceWord::SW => "SW",
+            DeviceWord::TN => "TN",
+            DeviceWord::CN => "CN",
+            DeviceWord::STN => "STN",
+            DeviceWord::Z => "Z",
+            DeviceWord::LTN => "LTN",
+            DeviceWord::LSTN => "LSTN",
+            DeviceWord::LCN => "LCN",
+            DeviceWord::LZ => "LZ",
+            DeviceWord::RD => "RD",
+        }
+    }
+
+    fn ascii(&self) -> &'static str {
+        match self {
+            DeviceWord::D => "D*",
+            DeviceWord::R => "R*",
+            DeviceWord::ZR => "ZR",
+            DeviceWord::W => "W*",
+            DeviceWord::SD => "SD",
+            DeviceWord::SW => "SW",
+            DeviceWord::TN => "TN",
+            DeviceWord::CN => "CN",
+            //STN 的 ASCII 代码为 SN
+            DeviceWord::STN => "SN",
+            DeviceWord::Z => "Z*",
+            DeviceWord::LTN => "LTN*",
+            DeviceWord::LSTN => "LSTN",
+            DeviceWord::LCN => "LCN*",
+            DeviceWord::LZ => "LZ**",
+            DeviceWord::RD => "RD**",
+        }
+    }
+
+    fn is_hex(&self) -> bool {
+        matches!(self, DeviceWord::W | DeviceWord::SW)
+    }
+
+    fn iqr_only(&self) -> bool {
+        matches!(
+            self,
+            DeviceWord::LTN | DeviceWord::LSTN | DeviceWord::LCN | DeviceWord::LZ | DeviceWord::RD
+        )
+    }
+}
+
+impl Device for DeviceBit {
+    fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            DeviceBit::X => "X",
+            DeviceBit::Y => "Y",
+            DeviceBit::M => "M",
+            DeviceBit::B => "B",
+            DeviceBit::L => "L",
+            DeviceBit::F => "F",
+            DeviceBit::V => "V",
+            DeviceBit::SM => "SM",
+            DeviceBit::SB => "SB",
+            DeviceBit::DX => "DX",
+            DeviceBit::DY => "DY",
+            DeviceBit::TS => "TS",
+            DeviceBit::TC => "TC",
+            DeviceBit::CS => "CS",
+            DeviceBit::CC => "CC",
+            DeviceBit::STS => "STS",
+            DeviceBit::STC => "STC",
+        }
+    }
+
+    fn ascii(&self) -> &'static str {
+        match self {
+            DeviceBit::X => "X*",
+            DeviceBit::Y => "Y*",
+            DeviceBit::M => "M*",
+            DeviceBit::B => "B*",
+            DeviceBit::L => "L*",
+            DeviceBit::F => "F*",
+            DeviceBit::V => "V*",
+            DeviceBit::SM => "SM",
+            DeviceBit::SB => "SB",
+            DeviceBit::DX => "DX",
+            DeviceBit::DY => "DY",
+            DeviceBit::TS => "TS",
+            DeviceBit::TC => "TC",
+            DeviceBit::CS => "CS",
+            DeviceBit::CC => "CC",
+            //STS/STC 的 ASCII 代码为 SS/SC
+            DeviceBit::STS => "SS",
+            DeviceBit::STC => "SC",
+        }
+    }
+
+    fn is_hex(&self) -> bool {
+        matches!(
+            self,
+            DeviceBit::X
+                | DeviceBit::Y
+                | DeviceBit::B
+                | DeviceBit::SB
+                | DeviceBit::DX
+                | DeviceBit::DY
+        )
+    }
+
+    fn is_read_only(&self) -> bool {
+        matches!(self, DeviceBit::TS | DeviceBit::CS | DeviceBit::STS)
+    }
 }
 
 //request 请求
 pub(crate) trait Req {
     //序列化
-    fn serialize(&self) -> Vec<u8>;
+    fn serialize(&self, head: &Header) -> Vec<u8>;
 }
 
 //response 响应
@@ -28,57 +240,627 @@ pub(crate) trait Res {
     //反序列化 Deserialization
     //如果报文结构正确，但是还不完整，返回 Ok(0)
     //如果报文结构正确并完整，返回 OK(l) l：有效报文长度
-    fn deserialization(&mut self, data: &[u8]) -> Result<u16, ()>;
+    //如果结束代码非零，返回 Err(SlmpError::PlcEndCode)
+    fn deserialization(&mut self, head: &Header, data: &[u8]) -> Result<u16, SlmpError>;
 }
 
+//3E帧副帧头
 const REQUSET: [u8; 2] = [0x50, 0x00];
 const RESPONSE: [u8; 2] = [0xD0, 0x00];
+//4E帧副帧头
+const REQUSET_4E: [u8; 2] = [0x54, 0x00];
+const RESPONSE_4E: [u8; 2] = [0xD4, 0x00];
+
+//报文格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameType {
+    Frame3E, //3E帧
+    Frame4E, //4E帧, 带序列号, 可以按序列号匹配请求和响应
+}
+
+//通信数据代码
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataCode {
+    Binary, //二进制代码
+    Ascii,  //ASCII代码, 数值以16进制文本表示
+}
+
+//CPU 系列, 决定软元件的编码方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuSeries {
+    QL,  //Q/L 系列, 子指令 0000/0001, 3字节软元件编号, 1字节软元件代码
+    IqR, //iQ-R 系列, 子指令 0002/0003, 4字节软元件编号, 2字节软元件代码
+}
+
+//远程 RUN 时的软元件清除模式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ClearMode {
+    #[default]
+    None, //不清除
+    ExceptLatch, //清除锁存范围以外的软元件
+    All,         //清除包括锁存范围在内的所有软元件
+}
+
+//单次批量读写的最大字软元件点数
+const MAX_WORD_POINTS: usize = 960;
+//单次批量读写的最大位软元件点数
+const MAX_BIT_POINTS: usize = 7168;
+//随机读取的最大点数(字 + 双字)
+const MAX_RANDOM_READ_POINTS: usize = 192;
+const MAX_RANDOM_READ_POINTS_IQR: usize = 96;
+//随机写入字软元件的最大点数: 字点数 * 12 + 双字点数 * 14 不超过此值
+const MAX_RANDOM_WRITE_SIZE: usize = 960;
+//随机写入位软元件的最大点数
+const MAX_RANDOM_BIT_POINTS: usize = 188;
+const MAX_RANDOM_BIT_POINTS_IQR: usize = 94;
+//折返测试的最大字节数
+const MAX_LOOPBACK_SIZE: usize = 960;
+//多块读写的最大块数
+const MAX_BLOCKS: usize = 120;
+
+//按通信数据代码写入请求报文
+//二进制代码: 数值按小端字节序写入
+//ASCII代码: 数值按高位在前写入16进制文本
+pub(crate) struct Writer {
+    code: DataCode,
+    series: CpuSeries,
+    out: Vec<u8>,
+}
+
+impl Writer {
+    fn new(code: DataCode) -> Writer {
+        Writer {
+            code,
+            series: CpuSeries::QL,
+            out: Vec::with_capacity(64),
+        }
+    }
+
+    //写入 width 位16进制文本
+    fn hex(&mut self, v: u32, width: usize) {
+        let s = format!("{:0width$X}", v, width = width);
+        self.out.extend_from_slice(s.as_bytes());
+    }
+
+    fn u8(&mut self, v: u8) {
+        match self.code {
+            DataCode::Binary => self.out.push(v),
+            DataCode::Ascii => self.hex(v as u32, 2),
+        }
+    }
+
+    fn u16(&mut self, v: u16) {
+        match self.code {
+            DataCode::Binary => self.out.extend_from_slice(&v.to_le_bytes()),
+            DataCode::Ascii => self.hex(v as u32, 4),
+        }
+    }
+
+    fn u32(&mut self, v: u32) {
+        match self.code {
+            DataCode::Binary => self.out.extend_from_slice(&v.to_le_bytes()),
+            DataCode::Ascii => self.hex(v, 8),
+        }
+    }
+
+    //原样写入的文本, 二进制代码时也不转换
+    fn text(&mut self, d: &[u8]) {
+        self.out.extend_from_slice(d);
+    }
+
+    //子指令, iQ-R 系列时 0000/0001 变为 0002/0003
+    fn subcommand(&mut self, sub: u16) {
+        match self.series {
+            CpuSeries::QL => self.u16(sub),
+            CpuSeries::IqR => self.u16(sub | 0x0002),
+        }
+    }
+
+    //起始软元件编号 + 软元件代码
+    //Q/L 二进制: 编号(3字节) + 代码(1字节)
+    //Q/L ASCII: 代码(2字符) + 编号(6字符, 10进制或16进制)
+    //iQ-R 二进制: 编号(4字节) + 代码(2字节)
+    //iQ-R ASCII: 代码(4字符, 不足补'*') + 编号(8字符)
+    fn device<D: Device>(&mut self, head_number: u32, dev: D) {
+        let (width, code) = match self.series {
+            CpuSeries::QL => (6, dev.ascii().to_string()),
+            CpuSeries::IqR => (8, format!("{:*<4}", dev.name())),
+        };
+        match self.code {
+            DataCode::Binary => {
+                self.out
+                    .extend_from_slice(&head_number.to_le_bytes()[..(width / 2)]);
+                self.out.push(dev.code());
+                if self.series == CpuSeries::IqR {
+                    self.out.push(0x00);
+                }
+            }
+            DataCode::Ascii => {
+                self.out.extend_from_slice(code.as_bytes());
+                let s = if dev.is_hex() {
+                    format!("{:0width$X}", head_number, width = width)
+                } else {
+                    format!("{:0width$}", head_number, width = width)
+                };
+                self.out.extend_from_slice(s.as_bytes());
+            }
+        }
+    }
+
+    //位软元件数据
+    //二进制: 每字节2点, 奇数点时最后半字节补0
+    //ASCII: 每点1字符 '0' 或 '1'
+    fn bits(&mut self, data: &[bool]) {
+        match self.code {
+            DataCode::Binary => {
+                for c in data.chunks(2) {
+                    let mut u = 0u8;
+                    if c[0] {
+                        u |= 0x10;
+                    }
+                    if c.len() > 1 && c[1] {
+                        u |= 0x01;
+                    }
+                    self.out.push(u);
+                }
+            }
+            DataCode::Ascii => {
+                for &b in data {
+                    self.out.push(if b { b'1' } else { b'0' });
+                }
+            }
+        }
+    }
+}
+
+//按通信数据代码读取响应报文
+pub(crate) struct Reader<'a> {
+    code: DataCode,
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(code: DataCode, data: &'a [u8]) -> Reader<'a> {
+        Reader { code, data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SlmpError> {
+        if self.pos + n > self.data.len() {
+            return Err(SlmpError::MalformedFrame);
+        }
+        let d = &self.data[self.pos..(self.pos + n)];
+        self.pos += n;
+        Ok(d)
+    }
+
+    //读取 width 位16进制文本
+    fn hex(&mut self, width: usize) -> Result<u32, SlmpError> {
+        let d = self.take(width)?;
+        let s = std::str::from_utf8(d).map_err(|_| SlmpError::MalformedFrame)?;
+        u32::from_str_radix(s, 16).map_err(|_| SlmpError::MalformedFrame)
+    }
+
+    fn u8(&mut self) -> Result<u8, SlmpError> {
+        match self.code {
+            DataCode::Binary => Ok(self.take(1)?[0]),
+            DataCode::Ascii => Ok(self.hex(2)? as u8),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, SlmpError> {
+        match self.code {
+            DataCode::Binary => {
+                let d = self.take(2)?;
+                Ok(u16::from_le_bytes([d[0], d[1]]))
+            }
+            DataCode::Ascii => Ok(self.hex(4)? as u16),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, SlmpError> {
+        match self.code {
+            DataCode::Binary => {
+                let d = self.take(4)?;
+                Ok(u32::from_le_bytes([d[0], d[1], d[2], d[3]]))
+            }
+            DataCode::Ascii => self.hex(8),
+        }
+    }
+
+    //读取 number 点位软元件数据
+    fn bits(&mut self, number: usize) -> Result<Vec<bool>, SlmpError> {
+        let mut out = Vec::with_capacity(number + 1);
+        match self.code {
+            DataCode::Binary => {
+                for &u in self.take(number.div_ceil(2))? {
+                    out.push((u & 0xf0) != 0);
+                    out.push((u & 0x0f) != 0);
+                }
+                //若读取数量为奇数,则最后一个bool值多余
+                out.truncate(number);
+            }
+            DataCode::Ascii => {
+                for &c in self.take(number)? {
+                    match c {
+                        b'0' => out.push(false),
+                        b'1' => out.push(true),
+                        _ => return Err(SlmpError::MalformedFrame),
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
 
-pub(crate) struct Destination {
+//目标地址
+//默认访问直接连接的站(网络 0, 站号 0xFF, 本站 CPU 0x03FF)
+//经由网络访问其它站或多 CPU 系统中的其它 CPU 时设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Destination {
     network: u8,           //网络编号
     station: u8,           //站号
     module: u16,           //模块IO编号
     multidrop_station: u8, //多点站号
 }
 
+impl Default for Destination {
+    fn default() -> Destination {
+        Destination::new()
+    }
+}
+
 impl Destination {
-    pub(crate) fn new() -> Destination {
+    pub const MODULE_OWN: u16 = 0x03FF; //本站 CPU
+    pub const MODULE_CPU1: u16 = 0x03E0; //多 CPU 系统 1号机
+    pub const MODULE_CPU2: u16 = 0x03E1; //多 CPU 系统 2号机
+    pub const MODULE_CPU3: u16 = 0x03E2; //多 CPU 系统 3号机
+    pub const MODULE_CPU4: u16 = 0x03E3; //多 CPU 系统 4号机
+
+    pub fn new() -> Destination {
         Destination {
             network: 0x00,
             station: 0xff,
-            module: 0x03ff,
+            module: Destination::MODULE_OWN,
             multidrop_station: 0x00,
         }
     }
 
-    fn serialize(&self) -> [u8; 5] {
+    //网络编号
+    pub fn network(mut self, network: u8) -> Destination {
+        self.network = network;
+        self
+    }
+
+    //站号
+    pub fn station(mut self, station: u8) -> Destination {
+        self.station = station;
+        self
+    }
+
+    //请求目标模块IO编号
+    pub fn module(mut self, module: u16) -> Destination {
+        self.module = module;
+        self
+    }
+
+    //请求目标多点站号
+    pub fn multidrop_station(mut self, multidrop_station: u8) -> Destination {
+        self.multidrop_station = multidrop_station;
+        self
+    }
+
+    fn serialize(&self, w: &mut Writer) {
         //网络编号(1) + 站号(1) + 模块编号(2) + 多点站号(1) = 5 字节
-        let mut out: [u8; 5] = [0; 5];
-        out[0] = self.network;
-        out[1] = self.station;
-        let t: [u8; 2] = self.module.to_le_bytes();
-        out[2] = t[0];
-        out[3] = t[1];
-        out[4] = self.multidrop_station;
-        return out;
+        w.u8(self.network);
+        w.u8(self.station);
+        w.u16(self.module);
+        w.u8(self.multidrop_station);
     }
 
-    fn deserialization(&mut self, data: &[u8]) -> Result<(), ()> {
+    fn deserialization(r: &mut Reader) -> Result<Destination, SlmpError> {
         //网络编号(1) + 站号(1) + 模块编号(2) + 多点站号(1) = 5 字节
-        if data.len() < 5 {
-            return Err(());
+        Ok(Destination {
+            network: r.u8()?,
+            station: r.u8()?,
+            module: r.u16()?,
+            multidrop_station: r.u8()?,
+        })
+    }
+}
+
+//报文头
+//副帧头 + [序列号 + 空闲] + 目标地址 + 数据长
+pub(crate) struct Header {
+    frame: FrameType,
+    code: DataCode,
+    serial: u16, //序列号, 只有4E帧使用
+    des: Destination,
+    timer: u16,        //监视定时器, 单位 250ms
+    series: CpuSeries, //CPU 系列
+}
+
+impl Header {
+    pub(crate) fn new(frame: FrameType, code: DataCode, serial: u16) -> Header {
+        Header {
+            frame,
+            code,
+            serial,
+            des: Destination::new(),
+            timer: 0,
+            series: CpuSeries::QL,
+        }
+    }
+
+    //设置 CPU 系列
+    pub(crate) fn with_series(mut self, series: CpuSeries) -> Header {
+        self.series = series;
+        self
+    }
+
+    //设置目标地址
+    pub(crate) fn with_destination(mut self, des: Destination) -> Header {
+        self.des = des;
+        self
+    }
+
+    //设置监视定时器, 不足 250ms 的部分进位, 最大 0xFFFF
+    pub(crate) fn with_monitoring_timer(mut self, timer: Duration) -> Header {
+        let t = timer.as_millis().div_ceil(250);
+        self.timer = t.min(u16::MAX as u128) as u16;
+        self
+    }
+
+    //序列号, 3E帧为 0
+    pub(crate) fn serial(&self) -> u16 {
+        self.serial
+    }
+
+    //数据长之前的长度
+    fn len_offset(&self) -> usize {
+        match (self.frame, self.code) {
+            (FrameType::Frame3E, DataCode::Binary) => 7,
+            (FrameType::Frame4E, DataCode::Binary) => 11,
+            (FrameType::Frame3E, DataCode::Ascii) => 14,
+            (FrameType::Frame4E, DataCode::Ascii) => 22,
+        }
+    }
+
+    //数据长字段的长度
+    fn len_size(&self) -> usize {
+        match self.code {
+            DataCode::Binary => 2,
+            DataCode::Ascii => 4,
+        }
+    }
+
+    //数据长之后的数据起始位置
+    fn data_offset(&self) -> usize {
+        self.len_offset() + self.len_size()
+    }
+
+    //副帧头, ASCII代码时为4个字符
+    fn subheader(&self, sub: [u8; 2]) -> Vec<u8> {
+        match self.code {
+            DataCode::Binary => sub.to_vec(),
+            DataCode::Ascii => format!("{:02X}{:02X}", sub[0], sub[1]).into_bytes(),
+        }
+    }
+
+    //生成写入了请求报文头的 Writer
+    //副帧头 + [序列号 + 空闲] + 目标地址 + 请求数据长(占位) + 监视定时器
+    fn writer(&self) -> Writer {
+        let mut w = Writer::new(self.code);
+        w.series = self.series;
+        //副帧头
+        match self.frame {
+            FrameType::Frame3E => w.out.extend(self.subheader(REQUSET)),
+            FrameType::Frame4E => {
+                w.out.extend(self.subheader(REQUSET_4E));
+                //序列号
+                w.u16(self.serial);
+                //空闲
+                w.u16(0);
+            }
+        }
+        //目标地址
+        self.des.serialize(&mut w);
+        //请求数据长,先占位
+        w.u16(0);
+        //监视定时器
+        w.u16(self.timer);
+        w
+    }
+
+    //请求数据写完后,修改请求数据长
+    fn finish(&self, w: Writer) -> Vec<u8> {
+        let mut out = w.out;
+        let p = self.len_offset();
+        let l = (out.len() - self.data_offset()) as u16;
+        let mut lw = Writer::new(self.code);
+        lw.u16(l);
+        out[p..(p + lw.out.len())].copy_from_slice(&lw.out);
+        out
+    }
+
+    //获取完整响应报文的长度
+    //报文不完整返回 Ok(None)
+    pub(crate) fn frame_len(&self, data: &[u8]) -> Result<Option<usize>, SlmpError> {
+        //检查副帧头
+        let sub = match self.frame {
+            FrameType::Frame3E => self.subheader(RESPONSE),
+            FrameType::Frame4E => self.subheader(RESPONSE_4E),
+        };
+        let n = sub.len().min(data.len());
+        if data[..n] != sub[..n] {
+            return Err(SlmpError::MalformedFrame);
+        }
+        let p = self.len_offset();
+        let d = self.data_offset();
+        if data.len() < d {
+            return Ok(None);
+        }
+        //获取响应数据长
+        let l = Reader::new(self.code, &data[p..d]).u16()? as usize;
+        if l < self.len_size() {
+            return Err(SlmpError::MalformedFrame);
+        }
+        let len = d + l;
+        if data.len() < len {
+            return Ok(None);
+        }
+        Ok(Some(len))
+    }
+
+    //获取响应报文的序列号, 3E帧返回 None
+    pub(crate) fn serial_of(&self, data: &[u8]) -> Option<u16> {
+        match self.frame {
+            FrameType::Frame3E => None,
+            FrameType::Frame4E => {
+                let s = self.subheader(RESPONSE_4E).len();
+                let mut r = Reader::new(self.code, data.get(s..)?);
+                r.u16().ok()
+            }
+        }
+    }
+
+    //检查响应报文是否是对本请求的响应
+    pub(crate) fn is_reply(&self, data: &[u8]) -> bool {
+        match self.frame {
+            FrameType::Frame3E => true,
+            FrameType::Frame4E => self.serial_of(data) == Some(self.serial),
+        }
+    }
+
+    //解析响应报文头
+    //报文不完整返回 Ok(None)
+    //报文完整返回 Ok(Some((报文长度, 响应数据))), 响应数据不含结束代码
+    //序列号不一致时返回 Err(SlmpError::MalformedFrame)
+    //目标地址与请求不一致时返回 Err(SlmpError::DestinationMismatch)
+    //结束代码非零时返回 Err(SlmpError::PlcEndCode)
+    fn deserialization<'a>(&self, data: &'a [u8]) -> Result<Option<(u16, Reader<'a>)>, SlmpError> {
+        let len = match self.frame_len(data)? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        //检查序列号
+        if !self.is_reply(data) {
+            return Err(SlmpError::MalformedFrame);
+        }
+        //检查地址
+        let d = self.data_offset();
+        let des_len = match self.code {
+            DataCode::Binary => 5,
+            DataCode::Ascii => 10,
+        };
+        let p = self.len_offset();
+        let des =
+            Destination::deserialization(&mut Reader::new(self.code, &data[(p - des_len)..p]))?;
+        if des != self.des {
+            return Err(SlmpError::DestinationMismatch);
+        }
+        //检查结束代码
+        let mut r = Reader::new(self.code, &data[d..len]);
+        let end_code = r.u16()?;
+        if end_code != 0 {
+            return Err(SlmpError::PlcEndCode {
+                code: PlcErrorCode::from_code(end_code),
+                error_info: deserialization_error_info(&mut r),
+            });
+        }
+        Ok(Some((len as u16, r)))
+    }
+}
+
+//解析错误信息
+//网络编号(1) + 站号(1) + 模块编号(2) + 多点站号(1) + 指令(2) + 子指令(2) = 9 字节
+//长度不足时返回 None
+fn deserialization_error_info(r: &mut Reader) -> Option<ErrorInfo> {
+    Some(ErrorInfo {
+        network: r.u8().ok()?,
+        station: r.u8().ok()?,
+        module: r.u16().ok()?,
+        multidrop_station: r.u8().ok()?,
+        command: r.u16().ok()?,
+        subcommand: r.u16().ok()?,
+    })
+}
+
+//检查软元件和起始软元件编号
+//编号的范围由编码方式决定, ASCII 10进制编号只有 6/8 个字符
+fn check_device<D: Device>(head: &Header, dev: D, head_number: u32) -> Result<(), SlmpError> {
+    if dev.iqr_only() && head.series != CpuSeries::IqR {
+        return Err(SlmpError::InvalidRequest(format!(
+            "device {} requires iQ-R series",
+            dev.name()
+        )));
+    }
+    let max = match (head.series, head.code, dev.is_hex()) {
+        (CpuSeries::QL, DataCode::Ascii, false) => 999_999,
+        (CpuSeries::QL, _, _) => 0xFF_FFFF,
+        (CpuSeries::IqR, DataCode::Ascii, false) => 99_999_999,
+        (CpuSeries::IqR, _, _) => u32::MAX,
+    };
+    if head_number > max {
+        return Err(SlmpError::InvalidRequest(format!(
+            "head number {} out of range",
+            head_number
+        )));
+    }
+    Ok(())
+}
+
+fn check_writable<D: Device>(dev: D) -> Result<(), SlmpError> {
+    if dev.is_read_only() {
+        return Err(SlmpError::InvalidRequest(format!(
+            "device {} is read-only",
+            dev.name()
+        )));
+    }
+    Ok(())
+}
+
+fn check_blocks(number: usize) -> Result<(), SlmpError> {
+    if number == 0 || number > MAX_BLOCKS {
+        return Err(SlmpError::InvalidRequest(format!(
+            "number of blocks {} not in 1..={}",
+            number, MAX_BLOCKS
+        )));
+    }
+    Ok(())
+}
+
+//随机读取的结果以地址为键, 重复的地址会丢失值
+fn check_unique(addrs: &[DeviceAddress]) -> Result<(), SlmpError> {
+    let mut seen = HashSet::new();
+    for a in addrs {
+        if !seen.insert(*a) {
+            return Err(SlmpError::InvalidRequest(format!(
+                "duplicate device address {}",
+                a
+            )));
         }
-        self.network = data[0];
-        self.station = data[1];
-        self.module = u16::from_le_bytes([data[2], data[3]]);
-        self.multidrop_station = data[4];
-        return Ok(());
     }
+    Ok(())
+}
+
+fn check_points(number: usize, max: usize) -> Result<(), SlmpError> {
+    if number == 0 || number > max {
+        return Err(SlmpError::InvalidRequest(format!(
+            "number of points {} not in 1..={}",
+            number, max
+        )));
+    }
+    Ok(())
 }
 
 //批量读请求(字软元件)
 struct ReqReadWords {
-    des: Destination,
     device: DeviceWord, //字元件类型
     head_number: u32,   //元件编号
     number: u16,        //元件数量
@@ -87,7 +869,6 @@ struct ReqReadWords {
 impl ReqReadWords {
     fn new(dev: DeviceWord) -> ReqReadWords {
         ReqReadWords {
-            des: Destination::new(),
             device: dev,
             head_number: 1,
             number: 1,
@@ -96,108 +877,50 @@ impl ReqReadWords {
 }
 
 impl Req for ReqReadWords {
-    fn serialize(&self) -> Vec<u8> {
-        let mut out: Vec<u8> = Vec::with_capacity(30);
-        //副帧头
-        for &i in &REQUSET {
-            out.push(i);
-        }
-        //目标地址
-        let d = self.des.serialize();
-        for &i in &d {
-            out.push(i);
-        }
-        //请求数据长
-        out.push(0x0c);
-        out.push(0x0);
-        //保留
-        for _i in 0..2 {
-            out.push(0);
-        }
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
         //指令
-        out.push(0x01);
-        out.push(0x04);
+        w.u16(0x0401);
         //子指令
-        out.push(0x00);
-        out.push(0x00);
-        //起始软元件编号
-        let h = self.head_number.to_le_bytes();
-        for i in 0..3 {
-            out.push(h[i]);
-        }
-        //软元件代码
-        out.push(self.device as u8);
+        w.subcommand(0x0000);
+        //起始软元件编号,软元件代码
+        w.device(self.head_number, self.device);
         //软元件点数
-        let n = self.number.to_le_bytes();
-        for i in 0..2 {
-            out.push(n[i]);
-        }
-
-        out
+        w.u16(self.number);
+        head.finish(w)
     }
 }
 
 //批量读响应(字软元件)
 struct ResReadWords {
-    des: Destination,
-    end_code: u16,  //结束代码
     data: Vec<u16>, //数据
 }
 
 impl ResReadWords {
     fn new() -> ResReadWords {
         ResReadWords {
-            des: Destination::new(),
-            end_code: 0,
             data: Vec::with_capacity(128),
         }
     }
 }
 
 impl Res for ResReadWords {
-    fn deserialization(&mut self, data: &[u8]) -> Result<u16, ()> {
-        if data.len() < 11 {
-            return Ok(0);
-        }
-        //检查副帧头
-        if data[0] != RESPONSE[0] || data[1] != RESPONSE[1] {
-            //      println!("副帧头 错误");
-            return Err(());
-        }
-        //检查地址
-        let r = self.des.deserialization(&data[2..=6]);
-        if r == Err(()) {
-            return Err(());
-        }
-        //获取响应数据长
-        let l: u16 = u16::from_le_bytes([data[7], data[8]]);
-        //报文长度
-        let len: u16 = l + 9;
-        if data.len() < (len as usize) {
-            return Ok(0);
-        }
-
-        //检查结束代码
-        self.end_code = u16::from_le_bytes([data[9], data[10]]);
-        if self.end_code != 0 {
-            self.data.clear();
-            return Ok(len);
-        }
+    fn deserialization(&mut self, head: &Header, data: &[u8]) -> Result<u16, SlmpError> {
+        let (len, mut r) = match head.deserialization(data)? {
+            Some(v) => v,
+            None => return Ok(0),
+        };
         //拷贝数据
-        for i in 0..(l / 2 - 1) {
-            let p1 = 11 + i * 2;
-            let p2 = p1 + 1;
-            let ul: [u8; 2] = [data[p1 as usize], data[p2 as usize]];
-            let v = u16::from_le_bytes(ul);
-            self.data.push(v);
+        self.data.clear();
+        while !r.is_empty() {
+            self.data.push(r.u16()?);
         }
-        return Ok(len);
+        Ok(len)
     }
 }
 
 //批量写请求(字软元件)
 struct ReqWriteWords {
-    des: Destination,
     device: DeviceWord,
     head_number: u32,
     data: Vec<u16>,
@@ -206,7 +929,6 @@ struct ReqWriteWords {
 impl ReqWriteWords {
     fn new(dev: DeviceWord) -> ReqWriteWords {
         ReqWriteWords {
-            des: Destination::new(),
             device: dev,
             head_number: 1,
             data: vec![],
@@ -215,370 +937,506 @@ impl ReqWriteWords {
 }
 
 impl Req for ReqWriteWords {
-    fn serialize(&self) -> Vec<u8> {
-        let mut out: Vec<u8> = Vec::with_capacity(32);
-        //副帧头
-        for &i in &REQUSET {
-            out.push(i);
-        }
-        //目标地址
-        let des = self.des.serialize();
-        for i in &des {
-            out.push(i.clone());
-        }
-        //请求数据长,先占位
-        out.push(0x0);
-        out.push(0x0);
-        //保留
-        for _i in 0..2 {
-            out.push(0);
-        }
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
         //指令
-        out.push(0x01);
-        out.push(0x14);
+        w.u16(0x1401);
         //子指令
-        out.push(0x00);
-        out.push(0x00);
-        //起始软元件编号
-        let h = self.head_number.to_le_bytes();
-        for i in 0..3 {
-            out.push(h[i]);
-        }
-        //软元件代码
-        out.push(self.device as u8);
+        w.subcommand(0x0000);
+        //起始软元件编号,软元件代码
+        w.device(self.head_number, self.device);
         //软元件点数
-        let n: [u8; 2] = (self.data.len() as u16).to_le_bytes();
-        for i in 0..2 {
-            out.push(n[i]);
-        }
+        w.u16(self.data.len() as u16);
         //数据
         for v in &self.data {
-            let l = v.to_le_bytes();
-            for i in 0..2 {
-                out.push(l[i]);
-            }
+            w.u16(*v);
         }
-        //修改数据长
-        let l = (out.len() - 9) as u16;
-        let lv = l.to_le_bytes();
-        out[7] = lv[0];
-        out[8] = lv[1];
-        out
+        head.finish(w)
     }
 }
 
-//批量写响应(字软元件)
-struct ResWriteWords {
-    des: Destination,
-    end_code: u16, //结束代码
+//只含结束代码的响应 (写入类指令)
+struct ResEmpty;
+
+impl ResEmpty {
+    fn new() -> ResEmpty {
+        ResEmpty
+    }
 }
 
-impl ResWriteWords {
-    fn new() -> ResWriteWords {
-        ResWriteWords {
-            des: Destination::new(),
-            end_code: 0,
+impl Res for ResEmpty {
+    fn deserialization(&mut self, head: &Header, data: &[u8]) -> Result<u16, SlmpError> {
+        match head.deserialization(data)? {
+            Some((len, _r)) => Ok(len),
+            None => Ok(0),
         }
     }
 }
 
-impl Res for ResWriteWords {
-    fn deserialization(&mut self, data: &[u8]) -> std::result::Result<u16, ()> {
-        if data.len() < 11 {
-            return Ok(0);
+//批量读多个块请求
+//字软元件块数 + 位软元件块数 <= 120 块
+//总点数 <= 960 点, 位软元件块按字(16点)计算
+struct ReqReadBlock {
+    words: Vec<(u32, DeviceWord, u16)>, //字软元件编号，软元件代码，软元件点数
+    bits: Vec<(u32, DeviceBit, u16)>,   //位软元件编号，软元件代码，字数(16点为1)
+}
+
+impl ReqReadBlock {
+    fn new() -> ReqReadBlock {
+        ReqReadBlock {
+            words: vec![],
+            bits: vec![],
         }
-        //检查副帧头
-        if data[0] != RESPONSE[0] || data[1] != RESPONSE[1] {
-            return Err(());
+    }
+}
+
+impl Req for ReqReadBlock {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
+        //指令
+        w.u16(0x0406);
+        //子指令
+        w.subcommand(0x0000);
+        //字软元件块数
+        w.u8(self.words.len() as u8);
+        //位软元件块数
+        w.u8(self.bits.len() as u8);
+        //字软元件
+        for (head_number, device, number) in &self.words {
+            //起始软元件编号,软元件代码
+            w.device(*head_number, *device);
+            //软元件点数
+            w.u16(*number);
         }
-        //检查地址
-        let r = self.des.deserialization(&data[2..=6]);
-        if r == Err(()) {
-            return Err(());
+        //位软元件
+        for (head_number, device, number) in &self.bits {
+            w.device(*head_number, *device);
+            w.u16(*number);
         }
-        //获取响应数据长
-        let l: u16 = u16::from_le_bytes([data[7], data[8]]);
 
-        //报文长度
-        let len: u16 = l + 9;
-        if data.len() < (len as usize) {
-            return Ok(0);
-        }
+        head.finish(w)
+    }
+}
 
-        //检查结束代码
-        self.end_code = u16::from_le_bytes([data[9], data[10]]);
+//批量读多个块的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Blocks {
+    pub words: Vec<Vec<u16>>, //字软元件块
+    pub bits: Vec<Vec<u16>>,  //位软元件块, 每个字为16点, 低位为起始软元件
+}
 
-        return Ok(len);
+//批量读多个块响应
+//字软元件块的数据在前, 位软元件块的数据在后
+struct ResReadBlock {
+    words: Vec<u16>, //每个字软元件块的点数
+    bits: Vec<u16>,  //每个位软元件块的字数
+    data: Blocks,
+}
+
+impl ResReadBlock {
+    fn new() -> ResReadBlock {
+        ResReadBlock {
+            words: vec![],
+            bits: vec![],
+            data: Blocks::default(),
+        }
     }
 }
 
-//批量读多个块请求(字软元件)
-//字软元件块数 <= 120 块
-//总字软元件点数 <= 960 点
-struct ReqReadBlockWord {
-    des: Destination,
-    data: Vec<(u32, DeviceWord, u16)>, //字软元件编号，软元件代码，软元件点数
-                                       //没有实现位软元件
+impl Res for ResReadBlock {
+    fn deserialization(&mut self, head: &Header, data: &[u8]) -> Result<u16, SlmpError> {
+        let (len, mut r) = match head.deserialization(data)? {
+            Some(v) => v,
+            None => return Ok(0),
+        };
+        //拷贝数据
+        let mut read = |numbers: &[u16]| -> Result<Vec<Vec<u16>>, SlmpError> {
+            numbers
+                .iter()
+                .map(|n| (0..*n).map(|_| r.u16()).collect())
+                .collect()
+        };
+        self.data.words = read(&self.words)?;
+        self.data.bits = read(&self.bits)?;
+        Ok(len)
+    }
 }
 
-impl ReqReadBlockWord {
-    fn new() -> ReqReadBlockWord {
-        ReqReadBlockWord {
-            des: Destination::new(),
-            data: vec![],
+//批量写多个块
+struct ReqWriteBlock {
+    words: Vec<(u32, DeviceWord, Vec<u16>)>, //字软元件编号,软元件代码,块数据
+    bits: Vec<(u32, DeviceBit, Vec<u16>)>,   //位软元件编号,软元件代码,块数据(每个字16点)
+}
+
+impl ReqWriteBlock {
+    fn new() -> ReqWriteBlock {
+        ReqWriteBlock {
+            words: vec![],
+            bits: vec![],
         }
     }
 }
 
-impl Req for ReqReadBlockWord {
-    fn serialize(&self) -> Vec<u8> {
-        let mut out: Vec<u8> = Vec::with_capacity(128);
-        //副帧头
-        for &i in &REQUSET {
-            out.push(i);
-        }
-        //目标地址
-        let d = self.des.serialize();
-        for &i in &d {
-            out.push(i);
-        }
-        //请求数据长,占位
-        out.push(0x0);
-        out.push(0x0);
-        //保留
-        for _i in 0..2 {
-            out.push(0);
-        }
+impl Req for ReqWriteBlock {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
         //指令
-        out.push(0x06);
-        out.push(0x04);
+        w.u16(0x1406);
         //子指令
-        out.push(0x00);
-        out.push(0x00);
+        w.subcommand(0x0000);
         //字软元件块数
-        out.push(self.data.len() as u8);
+        w.u8(self.words.len() as u8);
         //位软元件块数
-        out.push(0x00);
-        //字软元件
-        for (head_number, device, number) in &self.data {
-            //起始软元件编号
-            let h = head_number.to_le_bytes();
-            for i in 0..3 {
-                out.push(h[i]);
-            }
-            //软元件代码
-            out.push(device.clone() as u8);
+        w.u8(self.bits.len() as u8);
+
+        for (head_number, device, d) in &self.words {
+            //起始软元件编号,软元件代码
+            w.device(*head_number, *device);
             //软元件点数
-            let n = number.to_le_bytes();
-            for i in 0..2 {
-                out.push(n[i]);
+            w.u16(d.len() as u16);
+            //数据
+            for v in d {
+                w.u16(*v);
+            }
+        }
+        for (head_number, device, d) in &self.bits {
+            w.device(*head_number, *device);
+            w.u16(d.len() as u16);
+            for v in d {
+                w.u16(*v);
             }
         }
-        //不实现位软元件
-
-        //修改数据长
-        let l = (out.len() - 9) as u16;
-        let lv = l.to_le_bytes();
-        out[7] = lv[0];
-        out[8] = lv[1];
 
-        return out;
+        head.finish(w)
     }
 }
 
-//批量读多个块响应(字软元件)
-struct ResReadBlockWord {
-    des: Destination,
-    req_data: Vec<(DeviceWord, u16)>, //请求数据
-    end_code: u16,                    //结束代码
-    data: Vec<Vec<u16>>,              //只实现了字软元件
+//随机读取的结果, 键为请求的软元件地址
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RandomValues {
+    pub words: HashMap<DeviceAddress, u16>, //字访问, 位软元件为16点
+    pub dwords: HashMap<DeviceAddress, u32>, //双字访问
 }
 
-impl ResReadBlockWord {
-    fn new() -> ResReadBlockWord {
-        ResReadBlockWord {
-            des: Destination::new(),
-            req_data: vec![],
-            end_code: 0,
-            data: vec![],
-        }
-    }
+//随机读请求
+//监视登录(0801)的请求数据与随机读相同
+struct ReqReadRandom {
+    command: u16,               //指令
+    words: Vec<DeviceAddress>,  //字访问软元件
+    dwords: Vec<DeviceAddress>, //双字访问软元件
 }
 
-impl Res for ResReadBlockWord {
-    fn deserialization(&mut self, data: &[u8]) -> Result<u16, ()> {
-        if data.len() < 11 {
-            return Ok(0);
+impl ReqReadRandom {
+    fn new() -> ReqReadRandom {
+        ReqReadRandom {
+            command: 0x0403,
+            words: vec![],
+            dwords: vec![],
         }
-        //检查副帧头
-        if data[0] != RESPONSE[0] || data[1] != RESPONSE[1] {
-            return Err(());
-        }
-        //检查地址
-        let r = self.des.deserialization(&data[2..=6]);
-        if r == Err(()) {
-            return Err(());
-        }
-        //获取响应数据长
-        let l: u16 = u16::from_le_bytes([data[7], data[8]]);
-        //报文长度
-        let len: u16 = l + 9;
-        if data.len() < (len as usize) {
-            return Ok(0);
-        }
-        //检查结束代码
-        self.end_code = u16::from_le_bytes([data[9], data[10]]);
-        if self.end_code != 0 {
-            self.data.clear();
-            return Ok(len);
+    }
+
+    //监视登录
+    fn entry_monitor() -> ReqReadRandom {
+        ReqReadRandom {
+            command: 0x0801,
+            ..ReqReadRandom::new()
         }
-        //拷贝数据
-        let mut i = 0;
-        for (_device, number) in &self.req_data {
-            let mut block: Vec<u16> = Vec::new();
-            for _j in 0..*number {
-                let p1 = 11 + i * 2;
-                let p2 = p1 + 1;
-                i += 1;
-                if p2 >= len {
-                    return Err(());
-                }
-                let ul: [u8; 2] = [data[p1 as usize], data[p2 as usize]];
-                let v = u16::from_le_bytes(ul);
-                block.push(v);
-            }
-            self.data.push(block);
+    }
+}
+
+impl Req for ReqReadRandom {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
+        //指令
+        w.u16(self.command);
+        //子指令
+        w.subcommand(0x0000);
+        //字访问点数
+        w.u8(self.words.len() as u8);
+        //双字访问点数
+        w.u8(self.dwords.len() as u8);
+        //软元件编号,软元件代码
+        for a in self.words.iter().chain(self.dwords.iter()) {
+            w.device(a.number, a.device);
+        }
+        head.finish(w)
+    }
+}
+
+//远程操作请求
+//RUN(1001), STOP(1002), PAUSE(1003), 锁存清除(1005), RESET(1006)
+struct ReqRemote {
+    command: u16,
+    mode: u16,         //0001: 不强制执行, 0003: 强制执行
+    clear: Option<u8>, //RUN 时的清除模式
+}
+
+impl ReqRemote {
+    fn new(command: u16) -> ReqRemote {
+        ReqRemote {
+            command,
+            mode: 0x0001,
+            clear: None,
         }
-        return Ok(len);
     }
 }
 
-//批量写多个块(字软元件)
-struct ReqWriteBlockWord {
-    des: Destination,
-    data: Vec<(u32, DeviceWord, Vec<u16>)>, //字软元件编号,软元件代码,块数据
-                                            //位软元件不实现
+impl Req for ReqRemote {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
+        //指令
+        w.u16(self.command);
+        //子指令, 没有软元件, iQ-R 系列也为 0000
+        w.u16(0x0000);
+        //模式
+        w.u16(self.mode);
+        if let Some(clear) = self.clear {
+            //清除模式
+            w.u8(clear);
+            //固定值
+            w.u8(0x00);
+        }
+        head.finish(w)
+    }
+}
+
+//CPU 型号
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuInfo {
+    pub model: String,   //型号名称, 如 "Q03UDVCPU", 已去掉末尾的空格
+    pub model_code: u16, //型号代码
+}
+
+//读取型号请求
+struct ReqReadTypeName {}
+
+impl Req for ReqReadTypeName {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
+        //指令
+        w.u16(0x0101);
+        //子指令
+        w.u16(0x0000);
+        head.finish(w)
+    }
+}
+
+//读取型号响应
+//型号名称为16字节 ASCII 文本, 二进制代码时也是文本
+struct ResReadTypeName {
+    data: CpuInfo,
+}
+
+impl Res for ResReadTypeName {
+    fn deserialization(&mut self, head: &Header, data: &[u8]) -> Result<u16, SlmpError> {
+        let (len, mut r) = match head.deserialization(data)? {
+            Some(v) => v,
+            None => return Ok(0),
+        };
+        let model = r.take(16)?;
+        self.data.model = std::str::from_utf8(model)
+            .map_err(|_| SlmpError::MalformedFrame)?
+            .trim_end_matches([' ', '\0'])
+            .to_string();
+        self.data.model_code = r.u16()?;
+        Ok(len)
+    }
+}
+
+//折返测试请求
+//数据为 '0'-'9', 'A'-'F' 的文本, 二进制代码时也按文本发送
+struct ReqLoopback {
+    data: Vec<u8>,
+}
+
+impl Req for ReqLoopback {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
+        //指令
+        w.u16(0x0619);
+        //子指令
+        w.u16(0x0000);
+        //字节数
+        w.u16(self.data.len() as u16);
+        //数据
+        w.text(&self.data);
+        head.finish(w)
+    }
+}
+
+//折返测试响应
+struct ResLoopback {
+    data: Vec<u8>,
+}
+
+impl Res for ResLoopback {
+    fn deserialization(&mut self, head: &Header, data: &[u8]) -> Result<u16, SlmpError> {
+        let (len, mut r) = match head.deserialization(data)? {
+            Some(v) => v,
+            None => return Ok(0),
+        };
+        let n = r.u16()?;
+        self.data = Vec::from(r.take(n as usize)?);
+        Ok(len)
+    }
+}
+
+//远程密码解锁(1630)/锁定(1631)请求
+//Q/L 系列: 密码4个字符
+//iQ-R 系列: 密码长度 + 密码6-32个字符
+//密码在二进制代码时也按文本发送
+struct ReqPassword {
+    command: u16,
+    password: Vec<u8>,
+}
+
+impl Req for ReqPassword {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
+        //指令
+        w.u16(self.command);
+        //子指令
+        w.u16(0x0000);
+        if head.series == CpuSeries::IqR {
+            //密码长度
+            w.u16(self.password.len() as u16);
+        }
+        //密码
+        w.text(&self.password);
+        head.finish(w)
+    }
+}
+
+//执行监视请求
+//没有请求数据, 响应与随机读相同
+struct ReqExecuteMonitor {}
+
+impl Req for ReqExecuteMonitor {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
+        //指令
+        w.u16(0x0802);
+        //子指令, iQ-R 系列也为 0000
+        w.u16(0x0000);
+        head.finish(w)
+    }
+}
+
+//执行监视的结果, 按登录的顺序排列
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MonitorValues {
+    pub words: Vec<u16>,  //字访问, 位软元件为16点
+    pub dwords: Vec<u32>, //双字访问
+}
+
+//随机读响应
+//字访问的数据在前, 双字访问的数据在后
+struct ResReadRandom {
+    words: usize,     //字访问点数
+    dwords: usize,    //双字访问点数
+    data: Vec<u16>,   //字访问数据
+    data32: Vec<u32>, //双字访问数据
 }
 
-impl ReqWriteBlockWord {
-    fn new() -> ReqWriteBlockWord {
-        ReqWriteBlockWord {
-            des: Destination::new(),
+impl ResReadRandom {
+    fn new(words: usize, dwords: usize) -> ResReadRandom {
+        ResReadRandom {
+            words,
+            dwords,
             data: vec![],
+            data32: vec![],
         }
     }
 }
 
-impl Req for ReqWriteBlockWord {
-    fn serialize(&self) -> Vec<u8> {
-        let mut out: Vec<u8> = Vec::with_capacity(128);
-        //副帧头
-        for &i in &REQUSET {
-            out.push(i);
-        }
-        //目标地址
-        let des = self.des.serialize();
-        for i in &des {
-            out.push(i.clone());
-        }
-        //请求数据长,先占位
-        out.push(0x0);
-        out.push(0x0);
-        //保留
-        for _i in 0..2 {
-            out.push(0);
-        }
-        //指令
-        out.push(0x06);
-        out.push(0x14);
-        //子指令
-        out.push(0x00);
-        out.push(0x00);
-        //字软元件块数
-        out.push(self.data.len() as u8);
-        //位软元件块数
-        out.push(0x00);
-
-        for (head_number, device, d) in &self.data {
-            //起始软元件编号
-            let h = head_number.to_le_bytes();
-            for i in 0..3 {
-                out.push(h[i]);
-            }
-            //软元件代码
-            out.push(*device as u8);
-            //软元件点数
-            let n: [u8; 2] = (d.len() as u16).to_le_bytes();
-            for i in 0..2 {
-                out.push(n[i]);
-            }
-            //数据
-            for v in d {
-                let l = v.to_le_bytes();
-                for i in 0..2 {
-                    out.push(l[i]);
-                }
-            }
-        }
-
-        //修改数据长
-        let l = (out.len() - 9) as u16;
-        let lv = l.to_le_bytes();
-        out[7] = lv[0];
-        out[8] = lv[1];
-
-        return out;
+impl Res for ResReadRandom {
+    fn deserialization(&mut self, head: &Header, data: &[u8]) -> Result<u16, SlmpError> {
+        let (len, mut r) = match head.deserialization(data)? {
+            Some(v) => v,
+            None => return Ok(0),
+        };
+        self.data = (0..self.words).map(|_| r.u16()).collect::<Result<_, _>>()?;
+        self.data32 = (0..self.dwords)
+            .map(|_| r.u32())
+            .collect::<Result<_, _>>()?;
+        Ok(len)
     }
 }
 
-//批量写多个块响应(字软元件)
-struct ResWriteBlockWord {
-    des: Destination,
-    end_code: u16,
+//随机写请求(字, 双字)
+struct ReqWriteRandomWords {
+    words: Vec<(DeviceAddress, u16)>,  //字访问软元件和值
+    dwords: Vec<(DeviceAddress, u32)>, //双字访问软元件和值
 }
 
-impl ResWriteBlockWord {
-    fn new() -> ResWriteBlockWord {
-        ResWriteBlockWord {
-            des: Destination::new(),
-            end_code: 0,
+impl ReqWriteRandomWords {
+    fn new() -> ReqWriteRandomWords {
+        ReqWriteRandomWords {
+            words: vec![],
+            dwords: vec![],
         }
     }
 }
 
-impl Res for ResWriteBlockWord {
-    fn deserialization(&mut self, data: &[u8]) -> Result<u16, ()> {
-        if data.len() < 11 {
-            return Ok(0);
-        }
-        //检查副帧头
-        if data[0] != RESPONSE[0] || data[1] != RESPONSE[1] {
-            return Err(());
-        }
-        //检查地址
-        let r = self.des.deserialization(&data[2..=6]);
-        if r == Err(()) {
-            return Err(());
-        }
-        //获取响应数据长
-        let l: u16 = u16::from_le_bytes([data[7], data[8]]);
+impl Req for ReqWriteRandomWords {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
+        //指令
+        w.u16(0x1402);
+        //子指令
+        w.subcommand(0x0000);
+        //字访问点数
+        w.u8(self.words.len() as u8);
+        //双字访问点数
+        w.u8(self.dwords.len() as u8);
+        //软元件编号,软元件代码,数据
+        for (a, v) in &self.words {
+            w.device(a.number, a.device);
+            w.u16(*v);
+        }
+        for (a, v) in &self.dwords {
+            w.device(a.number, a.device);
+            w.u32(*v);
+        }
+        head.finish(w)
+    }
+}
 
-        //报文长度
-        let len: u16 = l + 9;
-        if data.len() < (len as usize) {
-            return Ok(0);
-        }
+//随机写请求(位)
+struct ReqWriteRandomBits {
+    data: Vec<(DeviceAddress, bool)>, //位软元件和值
+}
 
-        //检查结束代码
-        self.end_code = u16::from_le_bytes([data[9], data[10]]);
+impl ReqWriteRandomBits {
+    fn new() -> ReqWriteRandomBits {
+        ReqWriteRandomBits { data: vec![] }
+    }
+}
 
-        return Ok(len);
+impl Req for ReqWriteRandomBits {
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
+        //指令
+        w.u16(0x1402);
+        //子指令
+        w.subcommand(0x0001);
+        //位访问点数
+        w.u8(self.data.len() as u8);
+        //软元件编号,软元件代码,ON/OFF
+        //Q/L 系列为1字节, iQ-R 系列为2字节
+        for (a, v) in &self.data {
+            w.device(a.number, a.device);
+            match head.series {
+                CpuSeries::QL => w.u8(*v as u8),
+                CpuSeries::IqR => w.u16(*v as u16),
+            }
+        }
+        head.finish(w)
     }
 }
 
 //批量读请求(位软元件)
 struct ReqReadBits {
-    des: Destination,
     device: DeviceBit, //位元件类型
     head_number: u32,  //元件编号
     number: u16,       //元件数量
@@ -587,7 +1445,6 @@ struct ReqReadBits {
 impl ReqReadBits {
     fn new(dev: DeviceBit) -> ReqReadBits {
         ReqReadBits {
-            des: Destination::new(),
             device: dev,
             head_number: 1,
             number: 1,
@@ -596,109 +1453,49 @@ impl ReqReadBits {
 }
 
 impl Req for ReqReadBits {
-    fn serialize(&self) -> Vec<u8> {
-        let mut out: Vec<u8> = Vec::with_capacity(30);
-        //副帧头
-        for &i in &REQUSET {
-            out.push(i);
-        }
-        //目标地址
-        let d = self.des.serialize();
-        for &i in &d {
-            out.push(i);
-        }
-        //请求数据长
-        out.push(0x0c);
-        out.push(0x0);
-        //保留
-        for _i in 0..2 {
-            out.push(0);
-        }
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
         //指令
-        out.push(0x01);
-        out.push(0x04);
+        w.u16(0x0401);
         //子指令
-        out.push(0x01);
-        out.push(0x00);
-        //起始软元件编号
-        let h = self.head_number.to_le_bytes();
-        for i in 0..3 {
-            out.push(h[i]);
-        }
-        //软元件代码
-        out.push(self.device as u8);
+        w.subcommand(0x0001);
+        //起始软元件编号,软元件代码
+        w.device(self.head_number, self.device);
         //软元件点数
-        let n = self.number.to_le_bytes();
-        for i in 0..2 {
-            out.push(n[i]);
-        }
-
-        out
+        w.u16(self.number);
+        head.finish(w)
     }
 }
 
 //批量读响应(位软元件)
 struct ResReadBits {
-    des: Destination,
-    end_code: u16,   //结束代码
+    number: u16,     //请求的软元件点数
     data: Vec<bool>, //数据
 }
 
 impl ResReadBits {
-    fn new() -> ResReadBits {
+    fn new(number: u16) -> ResReadBits {
         ResReadBits {
-            des: Destination::new(),
-            end_code: 0,
+            number,
             data: Vec::with_capacity(128),
         }
     }
 }
 
 impl Res for ResReadBits {
-    fn deserialization(&mut self, data: &[u8]) -> Result<u16, ()> {
-        if data.len() < 11 {
-            return Ok(0);
-        }
-        //检查副帧头
-        if data[0] != RESPONSE[0] || data[1] != RESPONSE[1] {
-            //      println!("副帧头 错误");
-            return Err(());
-        }
-        //检查地址
-        let r = self.des.deserialization(&data[2..=6]);
-        if r == Err(()) {
-            return Err(());
-        }
-        //获取响应数据长
-        let l: u16 = u16::from_le_bytes([data[7], data[8]]);
-        //报文长度
-        let len: u16 = l + 9;
-        if data.len() < (len as usize) {
-            return Ok(0);
-        }
-
-        //检查结束代码
-        self.end_code = u16::from_le_bytes([data[9], data[10]]);
-        if self.end_code != 0 {
-            self.data.clear();
-            return Ok(len);
-        }
-
+    fn deserialization(&mut self, head: &Header, data: &[u8]) -> Result<u16, SlmpError> {
+        let (len, mut r) = match head.deserialization(data)? {
+            Some(v) => v,
+            None => return Ok(0),
+        };
         //拷贝数据
-        for i in 0..(l - 2) {
-            let u = data[(i + 11) as usize];
-            let b = (u & 0xf0) != 0;
-            self.data.push(b);
-            let b = (u & 0x0f) != 0;
-            self.data.push(b);
-        }
-        return Ok(len);
+        self.data = r.bits(self.number as usize)?;
+        Ok(len)
     }
 }
 
 //批量写请求(位软元件)
 struct ReqWriteBits {
-    des: Destination,
     device: DeviceBit,
     head_number: u32,
     data: Vec<bool>,
@@ -707,7 +1504,6 @@ struct ReqWriteBits {
 impl ReqWriteBits {
     fn new(dev: DeviceBit) -> ReqWriteBits {
         ReqWriteBits {
-            des: Destination::new(),
             device: dev,
             head_number: 1,
             data: vec![],
@@ -716,485 +1512,814 @@ impl ReqWriteBits {
 }
 
 impl Req for ReqWriteBits {
-    fn serialize(&self) -> Vec<u8> {
-        let mut out: Vec<u8> = Vec::with_capacity(32);
-        //副帧头
-        for &i in &REQUSET {
-            out.push(i);
-        }
-        //目标地址
-        let des = self.des.serialize();
-        for i in &des {
-            out.push(i.clone());
-        }
-        //请求数据长,先占位
-        out.push(0x0);
-        out.push(0x0);
-        //保留
-        for _i in 0..2 {
-            out.push(0);
-        }
+    fn serialize(&self, head: &Header) -> Vec<u8> {
+        let mut w = head.writer();
         //指令
-        out.push(0x01);
-        out.push(0x14);
+        w.u16(0x1401);
         //子指令
-        out.push(0x01);
-        out.push(0x00);
-        //起始软元件编号
-        let h = self.head_number.to_le_bytes();
-        for i in 0..3 {
-            out.push(h[i]);
-        }
-        //软元件代码
-        out.push(self.device as u8);
+        w.subcommand(0x0001);
+        //起始软元件编号,软元件代码
+        w.device(self.head_number, self.device);
         //软元件点数
-        let n: [u8; 2] = (self.data.len() as u16).to_le_bytes();
-        for i in 0..2 {
-            out.push(n[i]);
-        }
-
+        w.u16(self.data.len() as u16);
         //数据
-        for i in 0..(self.data.len() / 2) {
-            let mut u = 0u8;
-            if self.data[i * 2] {
-                u = u | 0x10;
-            }
-            if self.data[i * 2 + 1] {
-                u = u | 0x01;
-            }
-            out.push(u);
-        }
-        if self.data.len() % 2 == 1 {
-            let mut u = 0u8;
-            if *self.data.last().unwrap() {
-                u = u | 0x10;
-            }
-            out.push(u);
-        }
-
-        //修改数据长
-        let l = (out.len() - 9) as u16;
-        let lv = l.to_le_bytes();
-        out[7] = lv[0];
-        out[8] = lv[1];
-        out
-    }
-}
-
-//批量写响应(位软元件)
-struct ResWriteBits {
-    des: Destination,
-    end_code: u16, //结束代码
-}
-
-impl ResWriteBits {
-    fn new() -> ResWriteBits {
-        ResWriteBits {
-            des: Destination::new(),
-            end_code: 0,
-        }
+        w.bits(&self.data);
+        head.finish(w)
     }
 }
 
-impl Res for ResWriteBits {
-    fn deserialization(&mut self, data: &[u8]) -> std::result::Result<u16, ()> {
-        if data.len() < 11 {
-            return Ok(0);
-        }
-        //检查副帧头
-        if data[0] != RESPONSE[0] || data[1] != RESPONSE[1] {
-            return Err(());
-        }
-        //检查地址
-        let r = self.des.deserialization(&data[2..=6]);
-        if r == Err(()) {
-            return Err(());
-        }
-        //获取响应数据长
-        let l: u16 = u16::from_le_bytes([data[7], data[8]]);
-
-        //报文长度
-        let len: u16 = l + 9;
-        if data.len() < (len as usize) {
-            return Ok(0);
-        }
-
-        //检查结束代码
-        self.end_code = u16::from_le_bytes([data[9], data[10]]);
-
-        return Ok(len);
-    }
+//发送请求并等待响应
+async fn request<Q: Req, S: Res>(
+    conn: &Connection,
+    head: &Header,
+    req: &Q,
+    res: &mut S,
+) -> Result<(), SlmpError> {
+    let msg: Vec<u8> = req.serialize(head);
+    let reply = conn.request(head, &msg).await?;
+    res.deserialization(head, &reply)?;
+    Ok(())
 }
 
 // 批量读取字软元件
 // 读取成功返回 值数组
-// 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-// 其它错误都返回 Err(0)
 pub(crate) async fn read_words(
-    stream: &mut TcpStream,
+    conn: &Connection,
+    head: &Header,
     dev: DeviceWord,
     head_number: u32,
     number: u16,
-) -> Result<Vec<u16>, u16> {
-    let mut out = Result::Err(0);
+) -> Result<Vec<u16>, SlmpError> {
+    check_device(head, dev, head_number)?;
+    check_points(number as usize, MAX_WORD_POINTS)?;
     let mut req = ReqReadWords::new(dev);
     let mut res = ResReadWords::new();
     req.head_number = head_number;
     req.number = number;
-    let msg: Vec<u8> = req.serialize();
-    if let Err(_) = stream.write_all(&msg).await {
-        return out;
-    }
-    let mut buffer: Vec<u8> = Vec::with_capacity(128);
-
-    'a: loop {
-        let mut b = [0u8; 128];
-        let r = timeout(Duration::from_secs(2), async { stream.read(&mut b).await }).await;
-        match r {
-            Ok(0) => {
-                async_std::task::sleep(Duration::from_millis(100)).await;
-                continue 'a;
-            }
-            Ok(n) => {
-                for i in 0..n {
-                    buffer.push(b[i]);
-                }
-                match res.deserialization(&buffer) {
-                    Ok(0) => {
-                        //报文不完整
-                        continue;
-                    }
-                    Ok(_n) => {
-                        //已解析出完整报文
-                        if res.end_code != 0 {
-                            out = Result::Err(res.end_code);
-                            break 'a;
-                        }
-                        out = Result::Ok(res.data.clone());
-                        break 'a;
-                    }
-                    Err(_) => {
-                        //报文结构不正确
-                        return out;
-                    }
-                }
-            }
-            Err(_e) => {
-                return out;
-            }
-        }
+    request(conn, head, &req, &mut res).await?;
+    if res.data.len() != number as usize {
+        return Err(SlmpError::MalformedFrame);
     }
-    return out;
+    Ok(res.data)
 }
 
 // 批量读取位软元件
 // 读取成功返回 值数组
-// 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-// 其它错误都返回 Err(0)
 pub(crate) async fn read_bits(
-    stream: &mut TcpStream,
+    conn: &Connection,
+    head: &Header,
     dev: DeviceBit,
     head_number: u32,
     number: u16,
-) -> Result<Vec<bool>, u16> {
-    let mut out = Result::Err(0);
+) -> Result<Vec<bool>, SlmpError> {
+    check_device(head, dev, head_number)?;
+    check_points(number as usize, MAX_BIT_POINTS)?;
     let mut req = ReqReadBits::new(dev);
-    let mut res = ResReadBits::new();
+    let mut res = ResReadBits::new(number);
     req.head_number = head_number;
     req.number = number;
-    let msg: Vec<u8> = req.serialize();
-    if let Err(_) = stream.write_all(&msg).await {
-        return out;
-    }
-
-    let mut buffer: Vec<u8> = Vec::with_capacity(128);
-
-    'a: loop {
-        let mut b = [0u8; 128];
-        let r = timeout(Duration::from_secs(2), async { stream.read(&mut b).await }).await;
-        match r {
-            Ok(0) => {
-                async_std::task::sleep(Duration::from_millis(100)).await;
-                continue 'a;
-            }
-            Ok(n) => {
-                for i in 0..n {
-                    buffer.push(b[i]);
-                }
-                match res.deserialization(&buffer) {
-                    Ok(0) => {
-                        //报文不完整
-                        continue;
-                    }
-                    Ok(_n) => {
-                        //已解析出完整报文
-                        if res.end_code != 0 {
-                            out = Result::Err(res.end_code);
-                            break 'a;
-                        }
-                        if (number % 2) == 1 {
-                            //若读取数量为奇数,则最后一个bool值多余
-                            res.data.pop();
-                        }
-                        out = Result::Ok(res.data.clone());
-                        break 'a;
-                    }
-                    Err(_) => {
-                        //报文结构不正确
-                        return out;
-                    }
-                }
-            }
-            Err(_e) => {
-                return out;
-            }
-        }
-    }
-    return out;
+    request(conn, head, &req, &mut res).await?;
+    Ok(res.data)
 }
 
 // 批量写入字软元件
 // 写入成功返回 Ok
-// 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-// 其它错误都返回 Err(0)
 pub(crate) async fn write_words(
-    stream: &mut TcpStream,
+    conn: &Connection,
+    head: &Header,
     dev: DeviceWord,
     head_number: u32,
     data: &[u16],
-) -> Result<(), u16> {
-    let mut out = Result::Err(0);
+) -> Result<(), SlmpError> {
+    check_device(head, dev, head_number)?;
+    check_points(data.len(), MAX_WORD_POINTS)?;
     let mut req = ReqWriteWords::new(dev);
-    let mut res = ResWriteWords::new();
+    let mut res = ResEmpty::new();
     req.head_number = head_number;
     req.data = Vec::from(data);
-    let msg: Vec<u8> = req.serialize();
-    if let Err(_) = stream.write_all(&msg).await {
-        return out;
-    }
-    let mut buffer: Vec<u8> = Vec::with_capacity(128);
-
-    'a: loop {
-        let mut b = [0u8; 128];
-        let r = timeout(Duration::from_secs(2), async { stream.read(&mut b).await }).await;
-        match r {
-            Ok(0) => {
-                async_std::task::sleep(Duration::from_millis(100)).await;
-                continue 'a;
-            }
-            Ok(n) => {
-                for i in 0..n {
-                    buffer.push(b[i]);
-                }
-                match res.deserialization(&buffer) {
-                    Ok(0) => {
-                        //报文不完整
-                        continue 'a;
-                    }
-                    Ok(_n) => {
-                        //已解析出完整报文
-                        if res.end_code != 0 {
-                            out = Result::Err(res.end_code);
-                            break 'a;
-                        }
-                        out = Result::Ok(());
-                        break 'a;
-                    }
-                    Err(_) => {
-                        //报文结构不正确
-                        return out;
-                    }
-                }
-            }
-            Err(_e) => {
-                return out;
-            }
-        }
-    }
-    return out;
+    request(conn, head, &req, &mut res).await
 }
 
 // 批量写入位软元件
 // 写入成功返回 Ok
-// 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-// 其它错误都返回 Err(0)
 pub(crate) async fn write_bits(
-    stream: &mut TcpStream,
+    conn: &Connection,
+    head: &Header,
     dev: DeviceBit,
     head_number: u32,
     data: &[bool],
-) -> Result<(), u16> {
-    let mut out = Result::Err(0);
+) -> Result<(), SlmpError> {
+    check_device(head, dev, head_number)?;
+    check_writable(dev)?;
+    check_points(data.len(), MAX_BIT_POINTS)?;
     let mut req = ReqWriteBits::new(dev);
-    let mut res = ResWriteBits::new();
+    let mut res = ResEmpty::new();
     req.head_number = head_number;
     req.data = Vec::from(data);
-    let msg: Vec<u8> = req.serialize();
+    request(conn, head, &req, &mut res).await
+}
 
-    if let Err(_) = stream.write_all(&msg).await {
-        return out;
+// 批量读取多个块
+// 位软元件块按字访问, 点数为字数
+// 读取成功返回 字软元件块和位软元件块的值
+pub(crate) async fn read_blocks(
+    conn: &Connection,
+    head: &Header,
+    words: &[(u32, DeviceWord, u16)],
+    bits: &[(u32, DeviceBit, u16)],
+) -> Result<Blocks, SlmpError> {
+    check_blocks(words.len() + bits.len())?;
+    let mut req = ReqReadBlock::new();
+    let mut res = ResReadBlock::new();
+    let mut total = 0usize;
+    for (head_number, dev, number) in words {
+        check_device(head, *dev, *head_number)?;
+        total += *number as usize;
+        req.words.push((*head_number, *dev, *number));
+        res.words.push(*number);
     }
+    for (head_number, dev, number) in bits {
+        check_device(head, *dev, *head_number)?;
+        total += *number as usize;
+        req.bits.push((*head_number, *dev, *number));
+        res.bits.push(*number);
+    }
+    check_points(total, MAX_WORD_POINTS)?;
+    request(conn, head, &req, &mut res).await?;
+    Ok(res.data)
+}
 
-    let mut buffer: Vec<u8> = Vec::with_capacity(128);
+// 批量写多个块
+// 位软元件块按字写入, 每个字16点
+// 写入成功返回 Ok
+pub(crate) async fn write_blocks(
+    conn: &Connection,
+    head: &Header,
+    words: &[(u32, DeviceWord, Vec<u16>)],
+    bits: &[(u32, DeviceBit, Vec<u16>)],
+) -> Result<(), SlmpError> {
+    check_blocks(words.len() + bits.len())?;
+    let mut req = ReqWriteBlock::new();
+    let mut res = ResEmpty::new();
+    let mut total = 0usize;
+    for (head_number, dev, d) in words {
+        check_device(head, *dev, *head_number)?;
+        total += d.len();
+        req.words.push((*head_number, *dev, d.clone()));
+    }
+    for (head_number, dev, d) in bits {
+        check_device(head, *dev, *head_number)?;
+        check_writable(*dev)?;
+        total += d.len();
+        req.bits.push((*head_number, *dev, d.clone()));
+    }
+    check_points(total, MAX_WORD_POINTS)?;
+    request(conn, head, &req, &mut res).await
+}
 
-    'a: loop {
-        let mut b = [0u8; 128];
-        let r = timeout(Duration::from_secs(2), async { stream.read(&mut b).await }).await;
-        match r {
-            Ok(0) => {
-                async_std::task::sleep(Duration::from_millis(100)).await;
-                continue 'a;
-            }
-            Ok(n) => {
-                for i in 0..n {
-                    buffer.push(b[i]);
-                }
-                match res.deserialization(&buffer) {
-                    Ok(0) => {
-                        //报文不完整
-                        continue 'a;
-                    }
-                    Ok(_n) => {
-                        //已解析出完整报文
-                        if res.end_code != 0 {
-                            out = Result::Err(res.end_code);
-                            break 'a;
-                        }
-                        out = Result::Ok(());
-                        break 'a;
-                    }
-                    Err(_) => {
-                        //报文结构不正确
-                        return out;
-                    }
-                }
-            }
-            Err(_e) => {
-                return out;
-            }
-        }
+// 随机读取字和双字
+// 读取成功返回 按地址保存的值
+// 字访问或双字访问中有重复的地址时返回 Err(SlmpError::InvalidRequest)
+pub(crate) async fn read_random(
+    conn: &Connection,
+    head: &Header,
+    words: &[DeviceAddress],
+    dwords: &[DeviceAddress],
+) -> Result<RandomValues, SlmpError> {
+    let max = match head.series {
+        CpuSeries::QL => MAX_RANDOM_READ_POINTS,
+        CpuSeries::IqR => MAX_RANDOM_READ_POINTS_IQR,
+    };
+    check_points(words.len() + dwords.len(), max)?;
+    check_unique(words)?;
+    check_unique(dwords)?;
+    let mut req = ReqReadRandom::new();
+    for a in words.iter().chain(dwords.iter()) {
+        check_device(head, a.device, a.number)?;
     }
-    return out;
+    req.words = Vec::from(words);
+    req.dwords = Vec::from(dwords);
+    let mut res = ResReadRandom::new(words.len(), dwords.len());
+    request(conn, head, &req, &mut res).await?;
+    Ok(RandomValues {
+        words: words.iter().copied().zip(res.data).collect(),
+        dwords: dwords.iter().copied().zip(res.data32).collect(),
+    })
 }
 
-// 批量读取多个块 (字软元件）
-// 读取成功返回 值数组
-// 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-// 其它错误都返回 Err(0)
-pub(crate) async fn read_blocks(
-    stream: &mut TcpStream,
-    data: &Vec<(u32, DeviceWord, u16)>,
-) -> Result<Vec<Vec<u16>>, u16> {
-    let mut out = Result::Err(0);
-    let mut req = ReqReadBlockWord::new();
-    let mut res = ResReadBlockWord::new();
-    for (head_number, dev, number) in data {
-        req.data.push((*head_number, *dev, *number));
-        res.req_data.push((*dev, *number));
-    }
-
-    let msg: Vec<u8> = req.serialize();
-    if let Err(_) = stream.write_all(&msg).await {
-        return out;
-    }
-    let mut buffer: Vec<u8> = Vec::with_capacity(128);
-
-    'a: loop {
-        let mut b = [0u8; 256];
-        let r = timeout(Duration::from_secs(2), async { stream.read(&mut b).await }).await;
-        match r {
-            Ok(0) => {
-                async_std::task::sleep(Duration::from_millis(100)).await;
-                continue 'a;
-            }
-            Ok(n) => {
-                for i in 0..n {
-                    buffer.push(b[i]);
-                }
-                match res.deserialization(&buffer) {
-                    Ok(0) => {
-                        //报文不完整
-                        continue 'a;
-                    }
-                    Ok(_n) => {
-                        //已解析出完整报文
-                        if res.end_code != 0 {
-                            out = Result::Err(res.end_code);
-                            break 'a;
-                        }
-                        out = Result::Ok(res.data.clone());
-                        break 'a;
-                    }
-                    Err(_) => {
-                        //报文结构不正确
-                        return out;
-                    }
-                }
-            }
-            Err(_e) => {
-                return out;
-            }
-        }
+// 监视登录
+// 登录的软元件与随机读相同, 之后用 execute_monitor 读取
+pub(crate) async fn entry_monitor(
+    conn: &Connection,
+    head: &Header,
+    words: &[DeviceAddress],
+    dwords: &[DeviceAddress],
+) -> Result<(), SlmpError> {
+    let max = match head.series {
+        CpuSeries::QL => MAX_RANDOM_READ_POINTS,
+        CpuSeries::IqR => MAX_RANDOM_READ_POINTS_IQR,
+    };
+    check_points(words.len() + dwords.len(), max)?;
+    for a in words.iter().chain(dwords.iter()) {
+        check_device(head, a.device, a.number)?;
     }
-    return out;
+    let mut req = ReqReadRandom::entry_monitor();
+    req.words = Vec::from(words);
+    req.dwords = Vec::from(dwords);
+    let mut res = ResEmpty::new();
+    request(conn, head, &req, &mut res).await
+}
+
+// 执行监视
+// words, dwords 为登录的字访问点数和双字访问点数
+pub(crate) async fn execute_monitor(
+    conn: &Connection,
+    head: &Header,
+    words: usize,
+    dwords: usize,
+) -> Result<MonitorValues, SlmpError> {
+    let mut res = ResReadRandom::new(words, dwords);
+    request(conn, head, &ReqExecuteMonitor {}, &mut res).await?;
+    Ok(MonitorValues {
+        words: res.data,
+        dwords: res.data32,
+    })
 }
 
-// 批量写多个块 (字软元件)
+// 随机写入字和双字
 // 写入成功返回 Ok
-// 通信正常,slmp协议返回的结束代码非零时,返回 Err(end_code)
-// 其它错误都返回 Err(0)
-pub(crate) async fn write_blocks(
-    stream: &mut TcpStream,
-    data: &Vec<(u32, DeviceWord, Vec<u16>)>,
-) -> Result<(), u16> {
-    let mut out = Result::Err(0);
-    let mut req = ReqWriteBlockWord::new();
-    let mut res = ResWriteBlockWord::new();
-    for (head_number, dev, d) in data {
-        req.data.push((*head_number, *dev, d.clone()));
-    }
-    let msg: Vec<u8> = req.serialize();
-    if let Err(_) = stream.write_all(&msg).await {
-        return out;
-    }
-    let mut buffer: Vec<u8> = Vec::with_capacity(128);
-
-    'a: loop {
-        let mut b = [0u8; 256];
-        let r = timeout(Duration::from_secs(2), async { stream.read(&mut b).await }).await;
-        match r {
-            Ok(0) => {
-                async_std::task::sleep(Duration::from_millis(100)).await;
-                continue 'a;
-            }
-            Ok(n) => {
-                for i in 0..n {
-                    buffer.push(b[i]);
-                }
-                match res.deserialization(&buffer) {
-                    Ok(0) => {
-                        //报文不完整
-                        continue 'a;
-                    }
-                    Ok(_n) => {
-                        //已解析出完整报文
-                        if res.end_code != 0 {
-                            out = Result::Err(res.end_code);
-                            break 'a;
-                        }
-                        out = Result::Ok(());
-                        break 'a;
-                    }
-                    Err(_) => {
-                        //报文结构不正确
-                        return out;
-                    }
-                }
-            }
-            Err(_e) => {
-                return out;
+pub(crate) async fn write_random_words(
+    conn: &Connection,
+    head: &Header,
+    words: &[(DeviceAddress, u16)],
+    dwords: &[(DeviceAddress, u32)],
+) -> Result<(), SlmpError> {
+    let size = words.len() * 12 + dwords.len() * 14;
+    if size == 0 || size > MAX_RANDOM_WRITE_SIZE {
+        return Err(SlmpError::InvalidRequest(format!(
+            "{} words and {} dwords exceed random write limit",
+            words.len(),
+            dwords.len()
+        )));
+    }
+    let addrs = words.iter().map(|d| d.0).chain(dwords.iter().map(|d| d.0));
+    for a in addrs {
+        check_device(head, a.device, a.number)?;
+        check_writable(a.device)?;
+    }
+    let mut req = ReqWriteRandomWords::new();
+    req.words = Vec::from(words);
+    req.dwords = Vec::from(dwords);
+    request(conn, head, &req, &mut ResEmpty::new()).await
+}
+
+// 随机写入位软元件
+// 写入成功返回 Ok
+pub(crate) async fn write_random_bits(
+    conn: &Connection,
+    head: &Header,
+    data: &[(DeviceAddress, bool)],
+) -> Result<(), SlmpError> {
+    let max = match head.series {
+        CpuSeries::QL => MAX_RANDOM_BIT_POINTS,
+        CpuSeries::IqR => MAX_RANDOM_BIT_POINTS_IQR,
+    };
+    check_points(data.len(), max)?;
+    for (a, _) in data {
+        a.as_bit()?;
+        check_device(head, a.device, a.number)?;
+        check_writable(a.device)?;
+    }
+    let mut req = ReqWriteRandomBits::new();
+    req.data = Vec::from(data);
+    request(conn, head, &req, &mut ResEmpty::new()).await
+}
+
+// 读取 CPU 型号
+pub(crate) async fn read_type_name(conn: &Connection, head: &Header) -> Result<CpuInfo, SlmpError> {
+    let mut res = ResReadTypeName {
+        data: CpuInfo::default(),
+    };
+    request(conn, head, &ReqReadTypeName {}, &mut res).await?;
+    Ok(res.data)
+}
+
+// 折返测试
+// 返回 PLC 折返的数据, 与发送的不一致时返回 Err(SlmpError::MalformedFrame)
+pub(crate) async fn loopback(
+    conn: &Connection,
+    head: &Header,
+    data: &[u8],
+) -> Result<Vec<u8>, SlmpError> {
+    if data.is_empty() || data.len() > MAX_LOOPBACK_SIZE {
+        return Err(SlmpError::InvalidRequest(format!(
+            "loopback data length {} not in 1..={}",
+            data.len(),
+            MAX_LOOPBACK_SIZE
+        )));
+    }
+    if !data.iter().all(|c| matches!(c, b'0'..=b'9' | b'A'..=b'F')) {
+        return Err(SlmpError::InvalidRequest(
+            "loopback data must be 0-9, A-F".to_string(),
+        ));
+    }
+    let req = ReqLoopback {
+        data: Vec::from(data),
+    };
+    let mut res = ResLoopback { data: vec![] };
+    request(conn, head, &req, &mut res).await?;
+    if res.data != data {
+        return Err(SlmpError::MalformedFrame);
+    }
+    Ok(res.data)
+}
+
+// 远程密码解锁或锁定
+fn password_request(head: &Header, command: u16, password: &str) -> Result<ReqPassword, SlmpError> {
+    let valid = match head.series {
+        CpuSeries::QL => password.len() == 4,
+        CpuSeries::IqR => (6..=32).contains(&password.len()),
+    };
+    if !valid || !password.bytes().all(|c| c.is_ascii_graphic()) {
+        return Err(SlmpError::InvalidRequest(format!(
+            "password must be {} ascii characters",
+            match head.series {
+                CpuSeries::QL => "4",
+                CpuSeries::IqR => "6-32",
             }
+        )));
+    }
+    Ok(ReqPassword {
+        command,
+        password: Vec::from(password.as_bytes()),
+    })
+}
+
+// 远程密码解锁
+pub(crate) async fn unlock(
+    conn: &Connection,
+    head: &Header,
+    password: &str,
+) -> Result<(), SlmpError> {
+    let req = password_request(head, 0x1630, password)?;
+    request(conn, head, &req, &mut ResEmpty::new()).await
+}
+
+// 远程密码锁定
+pub(crate) async fn lock(
+    conn: &Connection,
+    head: &Header,
+    password: &str,
+) -> Result<(), SlmpError> {
+    let req = password_request(head, 0x1631, password)?;
+    request(conn, head, &req, &mut ResEmpty::new()).await
+}
+
+// 发送一次折返测试, 不重试, UDP 时也不重发
+// 成功返回 请求的往返时间
+pub(crate) async fn ping(conn: &Connection, head: &Header) -> Result<Duration, SlmpError> {
+    let req = ReqLoopback {
+        data: b"0123456789ABCDEF".to_vec(),
+    };
+    let mut res = ResLoopback { data: vec![] };
+    let msg = req.serialize(head);
+    let start = Instant::now();
+    let reply = conn.request_once(head, &msg).await?;
+    let rtt = start.elapsed();
+    res.deserialization(head, &reply)?;
+    if res.data != req.data {
+        return Err(SlmpError::MalformedFrame);
+    }
+    Ok(rtt)
+}
+
+// 远程 RUN
+// force 为 true 时, 其它设备远程 STOP/PAUSE 中也强制执行
+pub(crate) async fn remote_run(
+    conn: &Connection,
+    head: &Header,
+    force: bool,
+    clear: ClearMode,
+) -> Result<(), SlmpError> {
+    let mut req = ReqRemote::new(0x1001);
+    if force {
+        req.mode = 0x0003;
+    }
+    req.clear = Some(match clear {
+        ClearMode::None => 0x00,
+        ClearMode::ExceptLatch => 0x01,
+        ClearMode::All => 0x02,
+    });
+    request(conn, head, &req, &mut ResEmpty::new()).await
+}
+
+// 远程 STOP
+pub(crate) async fn remote_stop(conn: &Connection, head: &Header) -> Result<(), SlmpError> {
+    request(conn, head, &ReqRemote::new(0x1002), &mut ResEmpty::new()).await
+}
+
+// 远程 PAUSE
+// force 为 true 时, 其它设备远程 PAUSE 中也强制执行
+pub(crate) async fn remote_pause(
+    conn: &Connection,
+    head: &Header,
+    force: bool,
+) -> Result<(), SlmpError> {
+    let mut req = ReqRemote::new(0x1003);
+    if force {
+        req.mode = 0x0003;
+    }
+    request(conn, head, &req, &mut ResEmpty::new()).await
+}
+
+// 远程锁存清除, CPU 需为 STOP 状态
+pub(crate) async fn remote_latch_clear(conn: &Connection, head: &Header) -> Result<(), SlmpError> {
+    request(conn, head, &ReqRemote::new(0x1005), &mut ResEmpty::new()).await
+}
+
+// 远程 RESET, CPU 需为 STOP 状态
+// PLC 复位时可能不返回响应就断开, 请求发出后超时或连接被对方关闭视为成功
+// 请求没有发出时(写入失败等)返回错误
+pub(crate) async fn remote_reset(conn: &Connection, head: &Header) -> Result<(), SlmpError> {
+    let msg = ReqRemote::new(0x1006).serialize(head);
+    match conn.request_no_reply(head, &msg).await? {
+        Some(reply) => {
+            ResEmpty::new().deserialization(head, &reply)?;
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn test_end_code() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0);
+    let mut res = ResReadWords::new();
+    let frame = [
+        0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0B, 0x00, 0x51, 0xC0, 0x00, 0xFF, 0xFF, 0x03,
+        0x00, 0x01, 0x04, 0x00, 0x00,
+    ];
+    match res.deserialization(&head, &frame) {
+        Err(SlmpError::PlcEndCode { code, error_info }) => {
+            assert_eq!(code, PlcErrorCode::BitPointsOutOfRange);
+            assert_eq!(code.code(), 0xC051);
+            let info = error_info.unwrap();
+            assert_eq!(info.station, 0xFF);
+            assert_eq!(info.module, 0x03FF);
+            assert_eq!(info.command, 0x0401);
+            assert_eq!(info.subcommand, 0x0000);
+        }
+        _ => panic!("end code not reported"),
+    }
+    assert!(matches!(
+        res.deserialization(&head, &[0x50, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        Err(SlmpError::MalformedFrame)
+    ));
+}
+
+#[test]
+fn test_frame_4e() {
+    let head = Header::new(FrameType::Frame4E, DataCode::Binary, 0x1234);
+    let mut req = ReqReadWords::new(DeviceWord::D);
+    req.head_number = 100;
+    req.number = 2;
+    let msg = req.serialize(&head);
+    assert_eq!(
+        msg,
+        vec![
+            0x54, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0C, 0x00, 0x00,
+            0x00, 0x01, 0x04, 0x00, 0x00, 0x64, 0x00, 0x00, 0xA8, 0x02, 0x00
+        ]
+    );
+
+    let mut res = ResReadWords::new();
+    let mut frame = vec![
+        0xD4, 0x00, 0x34, 0x12, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x06, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x02, 0x00,
+    ];
+    assert_eq!(head.frame_len(&frame).unwrap(), Some(frame.len()));
+    assert_eq!(res.deserialization(&head, &frame).unwrap(), 19);
+    assert_eq!(res.data, vec![1, 2]);
+    //序列号不一致
+    frame[2] = 0x35;
+    assert!(!head.is_reply(&frame));
+    assert!(res.deserialization(&head, &frame).is_err());
+}
+
+#[test]
+fn test_ascii() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Ascii, 0);
+    let mut req = ReqReadWords::new(DeviceWord::D);
+    req.head_number = 100;
+    req.number = 2;
+    assert_eq!(
+        req.serialize(&head),
+        b"500000FF03FF000018000004010000D*0001000002".to_vec()
+    );
+
+    let mut req = ReqWriteBits::new(DeviceBit::X);
+    req.head_number = 0x1F;
+    req.data = vec![true, false, true];
+    assert_eq!(
+        req.serialize(&head),
+        b"500000FF03FF00001B000014010001X*00001F0003101".to_vec()
+    );
+
+    let frame = b"D00000FF03FF00000C000000010002";
+    assert_eq!(head.frame_len(frame).unwrap(), Some(frame.len()));
+    let mut res = ResReadWords::new();
+    res.deserialization(&head, frame).unwrap();
+    assert_eq!(res.data, vec![1, 2]);
+
+    let frame = b"D00000FF03FF0000070000101";
+    let mut res = ResReadBits::new(3);
+    res.deserialization(&head, frame).unwrap();
+    assert_eq!(res.data, vec![true, false, true]);
+
+    let frame = b"D00000FF03FF000016C05100FF03FF0004010000";
+    match ResEmpty::new().deserialization(&head, frame) {
+        Err(SlmpError::PlcEndCode { code, error_info }) => {
+            assert_eq!(code, PlcErrorCode::BitPointsOutOfRange);
+            assert_eq!(error_info.unwrap().command, 0x0401);
         }
+        _ => panic!("end code not reported"),
     }
-    return out;
+}
+
+#[test]
+fn test_destination() {
+    let des = Destination::new()
+        .network(1)
+        .station(2)
+        .module(Destination::MODULE_CPU2);
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0).with_destination(des);
+    let mut req = ReqReadWords::new(DeviceWord::D);
+    req.number = 1;
+    assert_eq!(
+        req.serialize(&head)[..9],
+        [0x50, 0x00, 0x01, 0x02, 0xE1, 0x03, 0x00, 0x0C, 0x00]
+    );
+
+    let mut frame = vec![
+        0xD0, 0x00, 0x01, 0x02, 0xE1, 0x03, 0x00, 0x04, 0x00, 0x00, 0x00, 0x07, 0x00,
+    ];
+    let mut res = ResReadWords::new();
+    res.deserialization(&head, &frame).unwrap();
+    assert_eq!(res.data, vec![7]);
+    //站号不一致
+    frame[3] = 0x03;
+    assert!(matches!(
+        res.deserialization(&head, &frame),
+        Err(SlmpError::DestinationMismatch)
+    ));
+}
+
+#[test]
+fn test_monitoring_timer() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0)
+        .with_monitoring_timer(Duration::from_millis(1100));
+    let msg = ReqReadWords::new(DeviceWord::D).serialize(&head);
+    assert_eq!(msg[9..11], [0x05, 0x00]);
+    let head = Header::new(FrameType::Frame3E, DataCode::Ascii, 0)
+        .with_monitoring_timer(Duration::from_secs(4));
+    let msg = ReqReadWords::new(DeviceWord::D).serialize(&head);
+    assert_eq!(&msg[18..22], b"0010");
+}
+
+#[test]
+fn test_device_word() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Ascii, 0);
+    let mut req = ReqReadWords::new(DeviceWord::W);
+    req.head_number = 0x1A0;
+    req.number = 1;
+    assert!(req.serialize(&head).ends_with(b"W*0001A00001"));
+    let mut req = ReqReadWords::new(DeviceWord::STN);
+    req.head_number = 10;
+    req.number = 1;
+    assert!(req.serialize(&head).ends_with(b"SN0000100001"));
+    assert!(matches!(
+        check_device(&head, DeviceWord::LTN, 0),
+        Err(SlmpError::InvalidRequest(_))
+    ));
+}
+
+#[test]
+fn test_device_bit() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Ascii, 0);
+    let mut req = ReqReadBits::new(DeviceBit::B);
+    req.head_number = 0x1F;
+    req.number = 1;
+    assert!(req.serialize(&head).ends_with(b"B*00001F0001"));
+    let mut req = ReqReadBits::new(DeviceBit::STS);
+    req.head_number = 10;
+    req.number = 1;
+    assert!(req.serialize(&head).ends_with(b"SS0000100001"));
+    assert!(check_writable(DeviceBit::TC).is_ok());
+    assert!(matches!(
+        check_writable(DeviceBit::TS),
+        Err(SlmpError::InvalidRequest(_))
+    ));
+}
+
+#[test]
+fn test_iqr() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0).with_series(CpuSeries::IqR);
+    let mut req = ReqReadWords::new(DeviceWord::LTN);
+    req.head_number = 0x0100_0000;
+    req.number = 2;
+    assert!(check_device(&head, req.device, req.head_number).is_ok());
+    assert_eq!(
+        req.serialize(&head)[11..],
+        [0x01, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x52, 0x00, 0x02, 0x00]
+    );
+
+    let head = Header::new(FrameType::Frame3E, DataCode::Ascii, 0).with_series(CpuSeries::IqR);
+    let mut req = ReqWriteBits::new(DeviceBit::X);
+    req.head_number = 0x1F;
+    req.data = vec![true];
+    assert!(req.serialize(&head).ends_with(b"14010003X***0000001F00011"));
+}
+
+#[test]
+fn test_read_random() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0);
+    let mut req = ReqReadRandom::new();
+    req.words = vec![
+        DeviceAddress::word(DeviceWord::D, 100),
+        DeviceAddress::bit(DeviceBit::M, 16),
+    ];
+    req.dwords = vec![DeviceAddress::word(DeviceWord::W, 0x2A)];
+    assert_eq!(
+        req.serialize(&head)[11..],
+        [
+            0x03, 0x04, 0x00, 0x00, 0x02, 0x01, 0x64, 0x00, 0x00, 0xA8, 0x10, 0x00, 0x00, 0x90,
+            0x2A, 0x00, 0x00, 0xB4
+        ]
+    );
+
+    let frame = [
+        0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00,
+        0x78, 0x56, 0x34, 0x12,
+    ];
+    let mut res = ResReadRandom::new(2, 1);
+    res.deserialization(&head, &frame).unwrap();
+    assert_eq!(res.data, vec![1, 2]);
+    assert_eq!(res.data32, vec![0x1234_5678]);
+
+    let d100 = DeviceAddress::word(DeviceWord::D, 100);
+    assert!(check_unique(&[d100, DeviceAddress::word(DeviceWord::D, 101)]).is_ok());
+    assert!(check_unique(&[d100, d100]).is_err());
+}
+
+#[test]
+fn test_write_random() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0);
+    let mut req = ReqWriteRandomWords::new();
+    req.words = vec![(DeviceAddress::word(DeviceWord::D, 0), 0x0550)];
+    req.dwords = vec![(DeviceAddress::word(DeviceWord::D, 1500), 0x1234_5678)];
+    assert_eq!(
+        req.serialize(&head)[11..],
+        [
+            0x02, 0x14, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0xA8, 0x50, 0x05, 0xDC, 0x05,
+            0x00, 0xA8, 0x78, 0x56, 0x34, 0x12
+        ]
+    );
+
+    let mut req = ReqWriteRandomBits::new();
+    req.data = vec![
+        (DeviceAddress::bit(DeviceBit::M, 50), false),
+        (DeviceAddress::bit(DeviceBit::Y, 0x2F), true),
+    ];
+    assert_eq!(
+        req.serialize(&head)[11..],
+        [
+            0x02, 0x14, 0x01, 0x00, 0x02, 0x32, 0x00, 0x00, 0x90, 0x00, 0x2F, 0x00, 0x00, 0x9D,
+            0x01
+        ]
+    );
+    let head = Header::new(FrameType::Frame3E, DataCode::Ascii, 0).with_series(CpuSeries::IqR);
+    assert!(req
+        .serialize(&head)
+        .ends_with(b"M***000000500000Y***0000002F0001"));
+}
+
+#[test]
+fn test_bit_blocks() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0);
+    let mut req = ReqReadBlock::new();
+    req.words = vec![(100, DeviceWord::D, 2)];
+    req.bits = vec![(0, DeviceBit::M, 1)];
+    assert_eq!(
+        req.serialize(&head)[11..],
+        [
+            0x06, 0x04, 0x00, 0x00, 0x01, 0x01, 0x64, 0x00, 0x00, 0xA8, 0x02, 0x00, 0x00, 0x00,
+            0x00, 0x90, 0x01, 0x00
+        ]
+    );
+
+    let frame = [
+        0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x08, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00,
+        0x05, 0x80,
+    ];
+    let mut res = ResReadBlock::new();
+    res.words = vec![2];
+    res.bits = vec![1];
+    res.deserialization(&head, &frame).unwrap();
+    assert_eq!(res.data.words, vec![vec![1, 2]]);
+    assert_eq!(res.data.bits, vec![vec![0x8005]]);
+}
+
+#[test]
+fn test_monitor() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0);
+    let mut req = ReqReadRandom::entry_monitor();
+    req.words = vec![DeviceAddress::word(DeviceWord::D, 100)];
+    req.dwords = vec![DeviceAddress::word(DeviceWord::W, 0x2A)];
+    assert_eq!(
+        req.serialize(&head)[11..],
+        [0x01, 0x08, 0x00, 0x00, 0x01, 0x01, 0x64, 0x00, 0x00, 0xA8, 0x2A, 0x00, 0x00, 0xB4]
+    );
+
+    let head = head.with_series(CpuSeries::IqR);
+    assert_eq!(
+        ReqExecuteMonitor {}.serialize(&head)[11..],
+        [0x02, 0x08, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn test_remote() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0);
+    let mut req = ReqRemote::new(0x1001);
+    req.mode = 0x0003;
+    req.clear = Some(0x02);
+    assert_eq!(
+        req.serialize(&head)[11..],
+        [0x01, 0x10, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00]
+    );
+    assert_eq!(
+        ReqRemote::new(0x1006).serialize(&head)[11..],
+        [0x06, 0x10, 0x00, 0x00, 0x01, 0x00]
+    );
+
+    let head = Header::new(FrameType::Frame3E, DataCode::Ascii, 0);
+    assert!(ReqRemote::new(0x1002)
+        .serialize(&head)
+        .ends_with(b"100200000001"));
+}
+
+#[test]
+fn test_read_type_name() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0);
+    assert_eq!(
+        ReqReadTypeName {}.serialize(&head)[11..],
+        [0x01, 0x01, 0x00, 0x00]
+    );
+
+    let mut frame = vec![
+        0xD0, 0x00, 0x00, 0xFF, 0xFF, 0x03, 0x00, 0x14, 0x00, 0x00, 0x00,
+    ];
+    frame.extend_from_slice(b"Q03UDVCPU       ");
+    frame.extend_from_slice(&[0x66, 0x03]);
+    let mut res = ResReadTypeName {
+        data: CpuInfo::default(),
+    };
+    res.deserialization(&head, &frame).unwrap();
+    assert_eq!(res.data.model, "Q03UDVCPU");
+    assert_eq!(res.data.model_code, 0x0366);
+}
+
+#[test]
+fn test_loopback() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0);
+    let req = ReqLoopback {
+        data: b"ABCDE".to_vec(),
+    };
+    assert_eq!(
+        req.serialize(&head)[11..],
+        [0x19, 0x06, 0x00, 0x00, 0x05, 0x00, b'A', b'B', b'C', b'D', b'E']
+    );
+
+    let head = Header::new(FrameType::Frame3E, DataCode::Ascii, 0);
+    let mut frame = b"D00000FF03FF00000D0000".to_vec();
+    frame.extend_from_slice(b"0005ABCDE");
+    let mut res = ResLoopback { data: vec![] };
+    res.deserialization(&head, &frame).unwrap();
+    assert_eq!(res.data, b"ABCDE");
+}
+
+#[test]
+fn test_password() {
+    let head = Header::new(FrameType::Frame3E, DataCode::Binary, 0);
+    let req = password_request(&head, 0x1630, "AB12").unwrap();
+    assert_eq!(
+        req.serialize(&head)[11..],
+        [0x30, 0x16, 0x00, 0x00, b'A', b'B', b'1', b'2']
+    );
+    assert!(password_request(&head, 0x1630, "ABCDEF").is_err());
+
+    let head = Header::new(FrameType::Frame3E, DataCode::Ascii, 0).with_series(CpuSeries::IqR);
+    let req = password_request(&head, 0x1631, "secret").unwrap();
+    assert!(req.serialize(&head).ends_with(b"163100000006secret"));
+    assert!(password_request(&head, 0x1631, "12345").is_err());
 }
@@ -0,0 +1,104 @@
+// 传输层
+// 指令层只处理完整的报文, 不关心报文通过 TCP 还是 UDP 传输
+use async_std::net::{TcpStream, UdpSocket};
+use async_std::{prelude::*, sync::Mutex as AsyncMutex};
+use std::future::Future;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
+use std::pin::Pin;
+
+pub(crate) type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+//传输方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportType {
+    Tcp, //TCP
+    Udp, //UDP, 一个数据报为一个完整的报文, 只能使用 4E 帧
+}
+
+pub(crate) trait Transport: Send + Sync {
+    //发送一个完整的请求报文
+    fn send<'a>(&'a self, msg: &'a [u8]) -> BoxFuture<'a, io::Result<()>>;
+
+    //接收数据, TCP 为字节流中的一段, UDP 为一个数据报
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, io::Result<usize>>;
+
+    fn close(&self);
+
+    //数据报传输, 报文可能丢失, 接收的数据不会跨数据报拼接
+    fn is_datagram(&self) -> bool;
+}
+
+pub(crate) struct Tcp {
+    stream: TcpStream,
+    write_lock: AsyncMutex<()>, //防止多个请求的报文交错写入
+}
+
+impl Tcp {
+    pub(crate) async fn connect(addr: &SocketAddr) -> io::Result<Tcp> {
+        let stream = TcpStream::connect(addr).await?;
+        let _ = stream.set_nodelay(true);
+        Ok(Tcp {
+            stream,
+            write_lock: AsyncMutex::new(()),
+        })
+    }
+}
+
+impl Transport for Tcp {
+    fn send<'a>(&'a self, msg: &'a [u8]) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            let _w = self.write_lock.lock().await;
+            (&self.stream).write_all(msg).await
+        })
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, io::Result<usize>> {
+        Box::pin(async move { (&self.stream).read(buf).await })
+    }
+
+    fn close(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    fn is_datagram(&self) -> bool {
+        false
+    }
+}
+
+pub(crate) struct Udp {
+    socket: UdpSocket,
+}
+
+impl Udp {
+    // 绑定本地任意端口, 只接收来自 addr 的数据报
+    pub(crate) async fn connect(addr: &SocketAddr) -> io::Result<Udp> {
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+        Ok(Udp { socket })
+    }
+}
+
+impl Transport for Udp {
+    fn send<'a>(&'a self, msg: &'a [u8]) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            self.socket.send(msg).await?;
+            Ok(())
+        })
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, io::Result<usize>> {
+        Box::pin(async move { self.socket.recv(buf).await })
+    }
+
+    //UDP 没有连接, 由连接取消后台读任务
+    fn close(&self) {}
+
+    fn is_datagram(&self) -> bool {
+        true
+    }
+}